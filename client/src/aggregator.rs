@@ -0,0 +1,181 @@
+//! Aggregator mode: one logical request is fanned out to several oracle contracts, and their
+//! answers for that round are combined once enough of them have arrived.
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::AccountId;
+use serde::{Deserialize, Serialize};
+
+/// How the answers of a round are combined
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AggregationMethod {
+    Median,
+    /// Mean after dropping the `trim` lowest and `trim` highest answers
+    TrimmedMean { trim: u64 },
+}
+
+impl AggregationMethod {
+    /// Combines the answers, which can't be empty
+    pub fn aggregate(&self, answers: &[u128]) -> u128 {
+        let mut sorted = answers.to_vec();
+        sorted.sort_unstable();
+        match self {
+            AggregationMethod::Median => median(&sorted),
+            AggregationMethod::TrimmedMean { trim } => {
+                let trim = *trim as usize;
+                assert!(trim * 2 < sorted.len(), "Not enough answers to trim.");
+                mean(&sorted[trim..sorted.len() - trim])
+            }
+        }
+    }
+}
+
+/// Median of sorted answers, rounded down between the two middle answers
+fn median(sorted: &[u128]) -> u128 {
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[middle]
+    } else {
+        let (low, high) = (sorted[middle - 1], sorted[middle]);
+        low + (high - low) / 2
+    }
+}
+
+fn mean(answers: &[u128]) -> u128 {
+    answers.iter().sum::<u128>() / answers.len() as u128
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AggregatorConfig {
+    pub oracles: Vec<AccountId>,
+    pub min_responses: u64,
+    pub method: AggregationMethod,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AggregatorConfigJSON {
+    pub oracles: Vec<AccountId>,
+    pub min_responses: U64,
+    pub method: AggregationMethod,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Round {
+    pub round_id: u64,
+    pub started_at: u64,
+    /// Zero until the round has its first aggregated answer
+    pub updated_at: u64,
+    pub answer: Option<u128>,
+    /// Answer of each oracle that responded, in the order they arrived
+    pub contributions: Vec<(AccountId, u128)>,
+    /// Taken from the aggregator config when the round started, so changing it doesn't affect the round
+    pub min_responses: u64,
+    pub method: AggregationMethod,
+}
+
+impl Round {
+    pub fn new(round_id: u64, started_at: u64, config: &AggregatorConfig) -> Self {
+        Self {
+            round_id,
+            started_at,
+            updated_at: 0,
+            answer: None,
+            contributions: Vec::new(),
+            min_responses: config.min_responses,
+            method: config.method.clone(),
+        }
+    }
+
+    /// Records the answer of `oracle` and aggregates the round once `min_responses` answers are in.
+    /// Later answers update the aggregated answer.
+    pub fn contribute(&mut self, oracle: AccountId, answer: u128, timestamp: u64) {
+        assert!(
+            self.contributions.iter().all(|(account, _)| *account != oracle),
+            "Oracle has already answered this round."
+        );
+        self.contributions.push((oracle, answer));
+        if self.contributions.len() as u64 >= self.min_responses {
+            let answers: Vec<u128> = self.contributions.iter().map(|(_, answer)| *answer).collect();
+            self.answer = Some(self.method.aggregate(&answers));
+            self.updated_at = timestamp;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ContributionJSON {
+    pub oracle: AccountId,
+    pub answer: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RoundJSON {
+    pub round_id: U64,
+    pub started_at: U64,
+    pub updated_at: Option<U64>,
+    pub answer: Option<U128>,
+    pub contributions: Vec<ContributionJSON>,
+    pub min_responses: U64,
+    pub method: AggregationMethod,
+}
+
+impl From<Round> for RoundJSON {
+    fn from(round: Round) -> Self {
+        Self {
+            round_id: round.round_id.into(),
+            started_at: round.started_at.into(),
+            updated_at: round.answer.map(|_| round.updated_at.into()),
+            answer: round.answer.map(U128),
+            contributions: round
+                .contributions
+                .into_iter()
+                .map(|(oracle, answer)| ContributionJSON { oracle, answer: answer.into() })
+                .collect(),
+            min_responses: round.min_responses.into(),
+            method: round.method,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_of_odd_and_even_answers() {
+        assert_eq!(20, AggregationMethod::Median.aggregate(&[30, 10, 20]));
+        assert_eq!(25, AggregationMethod::Median.aggregate(&[40, 10, 20, 30]));
+        assert_eq!(7, AggregationMethod::Median.aggregate(&[7]));
+    }
+
+    #[test]
+    fn trimmed_mean_drops_outliers() {
+        let method = AggregationMethod::TrimmedMean { trim: 1 };
+        assert_eq!(20, method.aggregate(&[1, 19, 20, 21, 1_000_000]));
+        assert_eq!(5, AggregationMethod::TrimmedMean { trim: 0 }.aggregate(&[4, 6]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough answers to trim.")]
+    fn trimmed_mean_needs_answers_left() {
+        AggregationMethod::TrimmedMean { trim: 1 }.aggregate(&[1, 2]);
+    }
+
+    #[test]
+    fn round_aggregates_after_min_responses() {
+        let config = AggregatorConfig {
+            oracles: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            min_responses: 2,
+            method: AggregationMethod::Median,
+        };
+        let mut round = Round::new(1, 5, &config);
+        round.contribute("a".to_string(), 100, 6);
+        assert_eq!(None, round.answer);
+        round.contribute("b".to_string(), 300, 7);
+        assert_eq!(Some(200), round.answer);
+        round.contribute("c".to_string(), 250, 8);
+        assert_eq!(Some(250), round.answer);
+        let json = RoundJSON::from(round);
+        assert_eq!(Some(U64(8)), json.updated_at);
+        assert_eq!(3, json.contributions.len());
+    }
+}
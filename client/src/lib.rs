@@ -1,20 +1,53 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use near_sdk::{env, ext_contract, near_bindgen, AccountId};
-use near_sdk::collections::TreeMap;
-use base64::{decode};
-use std::str;
-use near_sdk::json_types::U128;
+use serde::{Serialize, Deserialize};
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Balance, Promise, PromiseResult};
+use near_sdk::collections::{TreeMap, UnorderedSet};
+use near_sdk::json_types::{U128, U64};
+use serde_json::json;
 use std::collections::HashMap;
+use oracle_types::answers::{decode_text, decode_words, AnswerWord};
+use oracle_types::interfaces::{ext_link, ext_oracle};
+use oracle_types::requests::{request_id, RequestId};
+pub use oracle_types::Base64String;
+
+pub mod aggregator;
+use aggregator::{AggregationMethod, AggregatorConfig, AggregatorConfigJSON, Round, RoundJSON};
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 const SINGLE_CALL_GAS: u64 = 200_000_000_000_000;
+const ALLOWANCE_CALL_GAS: u64 = 10_000_000_000_000;
+const CALLBACK_GAS: u64 = 10_000_000_000_000;
+const STORAGE_WITHDRAW_GAS: u64 = 10_000_000_000_000;
+// enough for taking the storage deposit back out of NEAR LINK and refunding it
+const POST_REQUEST_GAS: u64 = CALLBACK_GAS + STORAGE_WITHDRAW_GAS + CALLBACK_GAS;
+// enough for the request and its callback
+const POST_ALLOWANCE_GAS: u64 = SINGLE_CALL_GAS + POST_REQUEST_GAS + CALLBACK_GAS;
+// enough for topping up the allowance and everything after it
+const REQUEST_WITH_ALLOWANCE_GAS: u64 = ALLOWANCE_CALL_GAS + POST_ALLOWANCE_GAS + CALLBACK_GAS;
+const MAX_TRANSACTION_GAS: u64 = 300_000_000_000_000;
+// aggregated requests go through ft_transfer_call, which stores the request without further calls
+const AGGREGATOR_REQUEST_GAS: u64 = 60_000_000_000_000;
+// request_aggregated_price itself, before sending any request
+const AGGREGATOR_ROUND_GAS: u64 = 20_000_000_000_000;
+// each oracle of a round takes its request and post_transfer_request, plus storing its nonce
+const AGGREGATOR_ORACLE_GAS: u64 = AGGREGATOR_REQUEST_GAS + CALLBACK_GAS + 5_000_000_000_000;
+const MAX_AGGREGATOR_ORACLES: usize = ((MAX_TRANSACTION_GAS - AGGREGATOR_ROUND_GAS) / AGGREGATOR_ORACLE_GAS) as usize;
 
-pub type Base64String = String;
+#[ext_contract(ext_self)]
+pub trait ExtClientContract {
+    fn request_with_allowance(&mut self, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: U128, required_allowance: U128);
+    fn post_allowance(&mut self, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: U128);
+    fn post_request(&mut self, nonce: U128, payment: U128, caller: AccountId, storage_deposit: U128);
+    fn post_storage_withdraw(&mut self, caller: AccountId, amount: U128);
+    fn post_transfer_request(&mut self, nonce: U128);
+}
 
-#[ext_contract(ext_oracle)]
-pub trait ExtOracleContract {
-    fn request(&mut self, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String);
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RequestStatus {
+    Pending,
+    Fulfilled,
+    Failed,
 }
 
 #[near_bindgen]
@@ -23,8 +56,21 @@ struct ClientContract {
     // Note: for this simple demo we'll store the oracle node in state like this
     // There's no reason why client contracts can't call various oracle contracts.
     oracle_account: AccountId,
+    // the client keeps its own allowance for the oracle on this token
+    link_account: AccountId,
     nonce: u128,
     received: TreeMap<u128, String>,
+    statuses: TreeMap<u128, RequestStatus>,
+    // nonces that were requested but not answered yet
+    outstanding: UnorderedSet<u128>,
+    // payments of get_token_price requests the oracle hasn't taken or rejected yet
+    pending_payments: Balance,
+    // aggregator mode, see aggregator.rs
+    aggregator: Option<AggregatorConfig>,
+    latest_round_id: u64,
+    rounds: TreeMap<u64, Round>,
+    // nonce of each aggregated request to its round and oracle
+    round_requests: TreeMap<u128, (u64, AccountId)>,
 }
 
 impl Default for ClientContract {
@@ -37,39 +83,224 @@ impl Default for ClientContract {
 impl ClientContract {
     #[allow(dead_code)]
     #[init]
-    pub fn new(oracle_account: AccountId) -> Self {
+    pub fn new(oracle_account: AccountId, link_account: AccountId) -> Self {
         Self {
             oracle_account,
+            link_account,
             nonce: 0_u128,
             received: TreeMap::new(b"r".to_vec()),
+            statuses: TreeMap::new(b"s".to_vec()),
+            outstanding: UnorderedSet::new(b"o".to_vec()),
+            pending_payments: 0,
+            aggregator: None,
+            latest_round_id: 0,
+            rounds: TreeMap::new(b"g".to_vec()),
+            round_requests: TreeMap::new(b"q".to_vec()),
         }
     }
 
-    /// symbol: Base64-encoded token symbol
-    #[allow(dead_code)] // This function gets called from the oracle
+    /// symbol: Base64-encoded request parameters, version 1 of the format in oracle-types
+    /// Checks the client's LINK allowance for the oracle first, and tops it up if it doesn't cover
+    /// the payments of all requests in flight. The attached deposit pays for the allowance's storage
+    /// on the token, and is refunded if no top up is needed.
+    #[allow(dead_code)]
+    #[payable]
     pub fn get_token_price(&mut self, symbol: String, spec_id: Base64String) -> U128 {
         // For the sake of demo, a few hardcoded values
         let payment = U128(10);
         self.nonce += 1;
         let nonce: U128 = self.nonce.into();
+        self.statuses.insert(&self.nonce, &RequestStatus::Pending);
+        self.outstanding.insert(&self.nonce);
+        // the allowance read below may not include requests that are still in flight
+        self.pending_payments += payment.0;
+        let required_allowance = U128(self.pending_payments);
 
-        ext_oracle::request(payment, spec_id, env::current_account_id(), "token_price_callback".to_string(), nonce, U128(1), symbol, &self.oracle_account, 0, SINGLE_CALL_GAS);
+        ext_link::get_allowance(env::current_account_id(), self.oracle_account.clone(), &self.link_account, 0, ALLOWANCE_CALL_GAS)
+            .then(ext_self::request_with_allowance(payment, spec_id, symbol, nonce, env::predecessor_account_id(), env::attached_deposit().into(), required_allowance, &env::current_account_id(), 0, REQUEST_WITH_ALLOWANCE_GAS));
         U128(self.nonce)
     }
 
+    /// Callback of the allowance check in get_token_price
+    #[allow(dead_code)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn request_with_allowance(&mut self, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: U128, required_allowance: U128) {
+        self._only_self_predecessor();
+        // a failed check is treated as no allowance, so the whole payment is approved
+        let mut allowance: u128 = 0;
+        if cfg!(target_arch = "wasm32") {
+            if let PromiseResult::Successful(value) = env::promise_result(0) {
+                allowance = serde_json::from_slice::<U128>(&value).map(|a| a.0).unwrap_or(0);
+            }
+        }
+        self._request_with_allowance(allowance, required_allowance.into(), payment, spec_id, symbol, nonce, caller, storage_deposit.into());
+    }
+
+    /// Callback of the allowance top up, only sends the request if the top up succeeded
+    #[allow(dead_code)]
+    pub fn post_allowance(&mut self, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: U128) {
+        self._only_self_predecessor();
+        let mut topped_up = true;
+        if cfg!(target_arch = "wasm32") {
+            topped_up = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        }
+        self._post_allowance(topped_up, payment, spec_id, symbol, nonce, caller, storage_deposit.into());
+    }
+
+    /// Callback of the oracle's `request`, marks the request as failed if it wasn't stored.
+    /// The caller's `storage_deposit`, only non-zero when the allowance was topped up, is then
+    /// taken back out of NEAR LINK and refunded.
+    #[allow(dead_code)]
+    pub fn post_request(&mut self, nonce: U128, payment: U128, caller: AccountId, storage_deposit: U128) {
+        self._only_self_predecessor();
+        let mut stored = true;
+        if cfg!(target_arch = "wasm32") {
+            stored = match env::promise_result(0) {
+                // request resolves to the request ID store_request returns, or null if it wasn't stored
+                PromiseResult::Successful(value) => serde_json::from_slice::<Option<RequestId>>(&value)
+                    .map_or(false, |id| id == Some(self._request_id(&self.oracle_account, nonce.0))),
+                _ => false,
+            };
+        }
+        self._post_request(nonce.into(), payment.into(), stored, caller, storage_deposit.into());
+    }
+
+    /// Callback of `storage_withdraw` on NEAR LINK, refunds the storage deposit of a failed request
+    /// once it's back on the client
+    #[allow(dead_code)]
+    pub fn post_storage_withdraw(&mut self, caller: AccountId, amount: U128) {
+        self._only_self_predecessor();
+        let mut withdrawn = true;
+        if cfg!(target_arch = "wasm32") {
+            withdrawn = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        }
+        self._post_storage_withdraw(withdrawn, caller, amount.into());
+    }
+
+    /// Callback of the aggregator's `ft_transfer_call`, marks the request as failed if it wasn't stored
+    #[allow(dead_code)]
+    pub fn post_transfer_request(&mut self, nonce: U128) {
+        self._only_self_predecessor();
+        let mut stored = true;
+        if cfg!(target_arch = "wasm32") {
+            stored = match env::promise_result(0) {
+                // ft_transfer_call returns the amount the oracle used, which is 0 if it rejected the request
+                PromiseResult::Successful(value) => serde_json::from_slice::<U128>(&value).map_or(false, |used| used.0 > 0),
+                _ => false,
+            };
+        }
+        self._complete_request(nonce.into(), stored);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_request_status(&self, nonce: U128) -> Option<RequestStatus> {
+        self.statuses.get(&nonce.into())
+    }
+
+    #[allow(dead_code)]
+    pub fn get_outstanding_nonces(&self) -> Vec<U128> {
+        self.outstanding.iter().map(U128).collect()
+    }
+
+    /// Only accepts answers from the oracle for outstanding nonces, so prices can't be spoofed.
+    /// Answers to aggregated requests have to come from the oracle the request was sent to.
+    /// A request ID, if the oracle sends one, has to be the one of the nonce on that oracle.
     #[allow(dead_code)] // This function gets called from the oracle
-    pub fn token_price_callback(&mut self, nonce: U128, answer: Base64String) {
-        let base64_price = match str::from_utf8(answer.as_bytes()) {
-            Ok(val) => val,
-            Err(_) => env::panic(b"Invalid UTF-8 sequence provided from oracle contract."),
-        };
-        let decoded_price_vec = decode(base64_price).unwrap();
-        let price_readable = match str::from_utf8(decoded_price_vec.as_slice()) {
-            Ok(val) => val,
-            Err(_) => env::panic(b"Invalid UTF-8 sequence in Base64 decoded value."),
+    pub fn token_price_callback(&mut self, nonce: U128, answer: Base64String, request_id: Option<RequestId>) {
+        let round_request = self.round_requests.get(&nonce.0);
+        let expected_oracle = match &round_request {
+            Some((_, oracle)) => oracle.clone(),
+            None => self.oracle_account.clone(),
         };
+        assert_eq!(env::predecessor_account_id(), expected_oracle, "Only the oracle can call this method.");
+        if let Some(request_id) = request_id {
+            assert_eq!(request_id, self._request_id(&expected_oracle, nonce.0), "Request ID does not match the nonce.");
+        }
+        if !self.outstanding.contains(&nonce.0) {
+            if self.received.get(&nonce.0).is_some() {
+                env::panic(format!("Nonce {} has already been answered.", nonce.0).as_bytes());
+            }
+            env::panic(format!("Nonce {} was not issued by this client.", nonce.0).as_bytes());
+        }
+        let price_readable = self._decode_price(&answer);
         env::log(format!("Client contract received price: {:?}", price_readable).as_bytes());
-        self.received.insert(&nonce.0, &price_readable.to_string());
+        self.received.insert(&nonce.0, &price_readable);
+        self.statuses.insert(&nonce.0, &RequestStatus::Fulfilled);
+        self.outstanding.remove(&nonce.0);
+        if let Some((round_id, oracle)) = round_request {
+            self._contribute(round_id, oracle, &price_readable);
+        }
+    }
+
+    /// Sets the oracles that aggregated requests are sent to, how many answers a round needs
+    /// and how they are combined
+    #[allow(dead_code)]
+    pub fn configure_aggregator(&mut self, oracles: Vec<AccountId>, min_responses: U64, method: AggregationMethod) {
+        self._only_self_predecessor();
+        let min_responses: u64 = min_responses.into();
+        assert!(!oracles.is_empty(), "At least one oracle is required.");
+        assert!(oracles.len() <= MAX_AGGREGATOR_ORACLES, "At most {} oracles can be configured.", MAX_AGGREGATOR_ORACLES);
+        assert!(min_responses > 0 && min_responses <= oracles.len() as u64, "Minimum responses must be between 1 and the number of oracles.");
+        if let AggregationMethod::TrimmedMean { trim } = method {
+            assert!(trim.checked_mul(2).map_or(false, |trimmed| trimmed < min_responses), "Trimming must leave at least one of the minimum responses.");
+        }
+        self.aggregator = Some(AggregatorConfig { oracles, min_responses, method });
+    }
+
+    #[allow(dead_code)]
+    pub fn get_aggregator_config(&self) -> Option<AggregatorConfigJSON> {
+        self.aggregator.as_ref().map(|config| AggregatorConfigJSON {
+            oracles: config.oracles.clone(),
+            min_responses: config.min_responses.into(),
+            method: config.method.clone(),
+        })
+    }
+
+    /// Starts a new round by sending the request to every configured oracle, paying each with
+    /// ft_transfer_call. Requires 1 yoctoNEAR attached per oracle. Returns the round ID.
+    #[allow(dead_code)]
+    #[payable]
+    pub fn request_aggregated_price(&mut self, symbol: String, spec_id: Base64String) -> U64 {
+        let (oracles, round) = match &self.aggregator {
+            Some(config) => (config.oracles.clone(), Round::new(self.latest_round_id + 1, env::block_timestamp(), config)),
+            None => env::panic(b"Aggregator is not configured."),
+        };
+        assert!(env::attached_deposit() >= oracles.len() as u128, "Requires attached deposit of 1 yoctoNEAR per oracle.");
+        // For the sake of demo, a hardcoded payment for each oracle
+        let payment = U128(10);
+        self.latest_round_id += 1;
+        let round_id = self.latest_round_id;
+        self.rounds.insert(&round_id, &round);
+
+        for oracle in oracles {
+            self.nonce += 1;
+            let nonce: U128 = self.nonce.into();
+            self.statuses.insert(&self.nonce, &RequestStatus::Pending);
+            self.outstanding.insert(&self.nonce);
+            self.round_requests.insert(&self.nonce, &(round_id, oracle.clone()));
+            let msg = json!({
+                "payment": payment,
+                "spec_id": spec_id,
+                "callback_address": env::current_account_id(),
+                "callback_method": "token_price_callback",
+                "nonce": nonce,
+                "data_version": U128(1),
+                "data": symbol,
+            }).to_string();
+            ext_link::ft_transfer_call(oracle, payment, None, msg, &self.link_account, 1, AGGREGATOR_REQUEST_GAS)
+                .then(ext_self::post_transfer_request(nonce, &env::current_account_id(), 0, CALLBACK_GAS));
+        }
+        U64(round_id)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_round(&self, round_id: U64) -> Option<RoundJSON> {
+        self.rounds.get(&round_id.into()).map(RoundJSON::from)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_latest_round(&self) -> Option<RoundJSON> {
+        self.rounds.get(&self.latest_round_id).map(RoundJSON::from)
     }
 
     // using String instead of U128 because
@@ -95,12 +326,118 @@ impl ClientContract {
     }
 }
 
+impl ClientContract {
+    #[allow(clippy::too_many_arguments)]
+    fn _request_with_allowance(&mut self, allowance: u128, required_allowance: u128, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: Balance) -> Promise {
+        if allowance < required_allowance {
+            // the deposit goes to the allowance's storage, post_allowance refunds it if the top up fails
+            env::log(format!("Increasing allowance of {} by {}", self.oracle_account, required_allowance - allowance).as_bytes());
+            ext_link::inc_allowance(self.oracle_account.clone(), U128(required_allowance - allowance), &self.link_account, storage_deposit, ALLOWANCE_CALL_GAS)
+                .then(ext_self::post_allowance(payment, spec_id, symbol, nonce, caller, U128(storage_deposit), &env::current_account_id(), 0, POST_ALLOWANCE_GAS))
+        } else {
+            if storage_deposit > 0 {
+                Promise::new(caller.clone()).transfer(storage_deposit);
+            }
+            self._request(payment, spec_id, symbol, nonce, caller, 0)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn _post_allowance(&mut self, topped_up: bool, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: Balance) {
+        if topped_up {
+            self._request(payment, spec_id, symbol, nonce, caller, storage_deposit);
+            return
+        }
+        // a failed inc_allowance returns the deposit to the client, so it can be refunded right away
+        env::log(format!("Allowance of {} could not be increased.", self.oracle_account).as_bytes());
+        self.pending_payments = self.pending_payments.saturating_sub(payment.0);
+        self._complete_request(nonce.into(), false);
+        if storage_deposit > 0 {
+            Promise::new(caller).transfer(storage_deposit);
+        }
+    }
+
+    /// Sends the request to the oracle, `storage_deposit` is what the caller paid for the top up
+    fn _request(&self, payment: U128, spec_id: Base64String, symbol: String, nonce: U128, caller: AccountId, storage_deposit: Balance) -> Promise {
+        ext_oracle::request(payment, spec_id, env::current_account_id(), "token_price_callback".to_string(), nonce, U128(1), symbol, None, &self.oracle_account, 0, SINGLE_CALL_GAS)
+            .then(ext_self::post_request(nonce, payment, caller, U128(storage_deposit), &env::current_account_id(), 0, POST_REQUEST_GAS))
+    }
+
+    fn _post_request(&mut self, nonce: u128, payment: Balance, stored: bool, caller: AccountId, storage_deposit: Balance) {
+        self.pending_payments = self.pending_payments.saturating_sub(payment);
+        self._complete_request(nonce, stored);
+        if !stored && storage_deposit > 0 {
+            // the deposit is now part of the client's storage balance on NEAR LINK
+            ext_link::storage_withdraw(Some(U128(storage_deposit)), &self.link_account, 1, STORAGE_WITHDRAW_GAS)
+                .then(ext_self::post_storage_withdraw(caller, U128(storage_deposit), &env::current_account_id(), 0, CALLBACK_GAS));
+        }
+    }
+
+    fn _post_storage_withdraw(&mut self, withdrawn: bool, caller: AccountId, amount: Balance) {
+        if withdrawn {
+            env::log(format!("Refunding the storage deposit of {} to {}", amount, caller).as_bytes());
+            Promise::new(caller).transfer(amount);
+        } else {
+            env::log(format!("Storage deposit of {} is in use on {} and can't be refunded.", amount, self.link_account).as_bytes());
+        }
+    }
+
+    /// Answers are either the price as plain text, or typed answer words starting with the price
+    fn _decode_price(&self, answer: &str) -> String {
+        if let Ok(words) = decode_words(answer) {
+            return match words.first() {
+                Some(AnswerWord::Int256(price)) | Some(AnswerWord::String(price)) => price.clone(),
+                _ => env::panic(b"Typed answers must start with the price."),
+            };
+        }
+        match decode_text(answer) {
+            Ok(price) => price,
+            Err(error) => env::panic(format!("Invalid answer from oracle contract: {}", error).as_bytes()),
+        }
+    }
+
+    fn _contribute(&mut self, round_id: u64, oracle: AccountId, answer: &str) {
+        let answer: u128 = match answer.parse() {
+            Ok(val) => val,
+            Err(_) => env::panic(b"Aggregated answers must be unsigned integers."),
+        };
+        let mut round = self.rounds.get(&round_id).unwrap();
+        round.contribute(oracle, answer, env::block_timestamp());
+        if let Some(aggregated) = round.answer {
+            env::log(format!("Round {} answer: {}", round_id, aggregated).as_bytes());
+        }
+        self.rounds.insert(&round_id, &round);
+    }
+
+    fn _complete_request(&mut self, nonce: u128, succeeded: bool) {
+        if !succeeded && self.statuses.get(&nonce) == Some(RequestStatus::Pending) {
+            env::log(format!("Request {} failed.", nonce).as_bytes());
+            self.statuses.insert(&nonce, &RequestStatus::Failed);
+            self.outstanding.remove(&nonce);
+        }
+    }
+
+    fn _request_id(&self, oracle: &AccountId, nonce: u128) -> RequestId {
+        request_id(oracle, &env::current_account_id(), nonce)
+    }
+
+    fn _only_self_predecessor(&self) {
+        assert_eq!(env::predecessor_account_id(), env::current_account_id(), "Only the contract itself can call this method.");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use near_sdk::{MockedBlockchain, StorageUsage};
     use near_sdk::{testing_env, VMContext};
     use base64::{encode};
+    use oracle_types::interfaces::{ext_consumer, ConsumerCallbackArgs};
+    use oracle_types::request_params::RequestParams;
+
+    fn eth_usd() -> Base64String {
+        RequestParams::new("https://min-api.cryptocompare.com/data/price?fsym=ETH&tsyms=USD").path("USD").times(100).to_base64()
+    }
 
     fn link() -> AccountId { "link_near".to_string() }
 
@@ -130,14 +467,250 @@ mod tests {
         }
     }
 
+    /// Compile-time check that the callback the oracle makes, `ConsumerCallbackArgs` and
+    /// `ext_consumer` in oracle-types, matches `token_price_callback`
+    #[test]
+    fn consumer_interface_matches_contract() {
+        let _token_price_callback = |contract: &mut ClientContract, args: ConsumerCallbackArgs| {
+            ext_consumer::token_price_callback(args.nonce, args.answer.clone(), args.request_id.clone(), &alice(), 0, 0);
+            contract.token_price_callback(args.nonce, args.answer, args.request_id)
+        };
+    }
+
     #[test]
     fn test_token_price() {
         let context = get_context(alice(), 0);
         testing_env!(context);
-        let mut contract = ClientContract::new(oracle() );
-        let mut returned_nonce = contract.get_token_price("eyJnZXQiOiJodHRwczovL21pbi1hcGkuY3J5cHRvY29tcGFyZS5jb20vZGF0YS9wcmljZT9mc3ltPUVUSCZ0c3ltcz1VU0QiLCJwYXRoIjoiVVNEIiwidGltZXMiOjEwMH0".to_string(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        let mut contract = ClientContract::new(oracle(), link());
+        let mut returned_nonce = contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
         assert_eq!(U128(1), returned_nonce);
-        returned_nonce = contract.get_token_price("eyJnZXQiOiJodHRwczovL21pbi1hcGkuY3J5cHRvY29tcGFyZS5jb20vZGF0YS9wcmljZT9mc3ltPUVUSCZ0c3ltcz1VU0QiLCJwYXRoIjoiVVNEIiwidGltZXMiOjEwMH0".to_string(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        returned_nonce = contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
         assert_eq!(U128(2), returned_nonce);
     }
+
+    #[test]
+    fn test_request_status() {
+        let mut context = get_context(alice(), 0);
+        context.account_balance = 10u128.pow(24);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        let symbol = eth_usd();
+        let first_nonce = contract.get_token_price(symbol.clone(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        let second_nonce = contract.get_token_price(symbol.clone(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        assert_eq!(Some(RequestStatus::Pending), contract.get_request_status(first_nonce));
+        assert_eq!(None, contract.get_request_status(U128(3)));
+
+        // tops up the allowance, or refunds the storage deposit
+        contract._request_with_allowance(0, 10, U128(10), "dW5pcXVlIHNwZWMgaWQ=".to_string(), symbol.clone(), first_nonce, bob(), 1);
+        contract._request_with_allowance(20, 20, U128(10), "dW5pcXVlIHNwZWMgaWQ=".to_string(), symbol, second_nonce, bob(), 1);
+
+        let mut context = get_context(alice(), env::storage_usage());
+        context.predecessor_account_id = oracle();
+        testing_env!(context);
+        contract.token_price_callback(first_nonce, encode("19.1"), None);
+        contract._complete_request(first_nonce.into(), true);
+        contract._complete_request(second_nonce.into(), false);
+        assert_eq!(Some(RequestStatus::Fulfilled), contract.get_request_status(first_nonce));
+        assert_eq!(Some(RequestStatus::Failed), contract.get_request_status(second_nonce));
+        assert_eq!("19.1".to_string(), contract.get_received_val(first_nonce));
+    }
+
+    #[test]
+    fn test_pending_payments() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        let first_nonce = contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        // the second request's allowance check has to cover the first one too
+        contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        assert_eq!(20, contract.pending_payments);
+        contract._post_request(first_nonce.into(), 10, true, bob(), 0);
+        assert_eq!(10, contract.pending_payments);
+    }
+
+    #[test]
+    fn test_failed_top_up_refunds_storage_deposit() {
+        let mut context = get_context(alice(), 0);
+        context.account_balance = 10u128.pow(24);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        let nonce = contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+
+        // the deposit came back from NEAR LINK with the failed inc_allowance
+        let balance = env::account_balance();
+        contract._post_allowance(false, U128(10), "dW5pcXVlIHNwZWMgaWQ=".to_string(), eth_usd(), nonce, bob(), 100);
+        assert_eq!(balance - 100, env::account_balance());
+        assert_eq!(Some(RequestStatus::Failed), contract.get_request_status(nonce));
+        assert_eq!(0, contract.pending_payments);
+    }
+
+    #[test]
+    fn test_failed_request_after_top_up_withdraws_storage_deposit() {
+        let mut context = get_context(alice(), 0);
+        context.account_balance = 10u128.pow(24);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        let first_nonce = contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        let second_nonce = contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        contract._post_allowance(true, U128(10), "dW5pcXVlIHNwZWMgaWQ=".to_string(), eth_usd(), first_nonce, bob(), 100);
+        contract._post_allowance(true, U128(10), "dW5pcXVlIHNwZWMgaWQ=".to_string(), eth_usd(), second_nonce, bob(), 100);
+
+        // the deposit is on NEAR LINK, so only the 1 yoctoNEAR for storage_withdraw is attached
+        let balance = env::account_balance();
+        contract._post_request(first_nonce.into(), 10, true, bob(), 100);
+        contract._post_request(second_nonce.into(), 10, false, bob(), 100);
+        assert_eq!(balance - 1, env::account_balance());
+        assert_eq!(Some(RequestStatus::Failed), contract.get_request_status(second_nonce));
+        assert_eq!(0, contract.pending_payments);
+
+        // it's refunded once storage_withdraw brought it back
+        contract._post_storage_withdraw(false, bob(), 100);
+        assert_eq!(balance - 1, env::account_balance());
+        contract._post_storage_withdraw(true, bob(), 100);
+        assert_eq!(balance - 101, env::account_balance());
+    }
+
+    fn requested_contract() -> ClientContract {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        contract.get_token_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string());
+        let mut context = get_context(alice(), env::storage_usage());
+        context.predecessor_account_id = oracle();
+        testing_env!(context);
+        contract
+    }
+
+    #[test]
+    fn test_outstanding_nonces() {
+        let mut contract = requested_contract();
+        assert_eq!(vec![U128(1)], contract.get_outstanding_nonces());
+        contract.token_price_callback(U128(1), encode("19.1"), Some(request_id(&oracle(), &env::current_account_id(), 1)));
+        assert!(contract.get_outstanding_nonces().is_empty());
+    }
+
+    #[test]
+    fn test_callback_typed_answer() {
+        let mut contract = requested_contract();
+        let answer = oracle_types::answers::encode_words(&[AnswerWord::Int256("1910".to_string()), AnswerWord::Bool(true)]);
+        contract.token_price_callback(U128(1), answer, None);
+        assert_eq!("1910".to_string(), contract.get_received_val(U128(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid answer from oracle contract: Answer is not valid UTF-8")]
+    fn test_callback_invalid_answer() {
+        let mut contract = requested_contract();
+        contract.token_price_callback(U128(1), encode(&[0xff, 0xfe]), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Request ID does not match the nonce.")]
+    fn test_callback_wrong_request_id() {
+        let mut contract = requested_contract();
+        contract.token_price_callback(U128(1), encode("19.1"), Some(request_id(&oracle(), &env::current_account_id(), 2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the oracle can call this method.")]
+    fn test_callback_not_from_oracle() {
+        let mut contract = requested_contract();
+        testing_env!(get_context(bob(), env::storage_usage()));
+        contract.token_price_callback(U128(1), encode("19.1"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nonce 2 was not issued by this client.")]
+    fn test_callback_unknown_nonce() {
+        let mut contract = requested_contract();
+        contract.token_price_callback(U128(2), encode("19.1"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nonce 1 has already been answered.")]
+    fn test_callback_answered_twice() {
+        let mut contract = requested_contract();
+        contract.token_price_callback(U128(1), encode("19.1"), None);
+        contract.token_price_callback(U128(1), encode("1000"), None);
+    }
+
+    fn aggregator_contract() -> ClientContract {
+        let mut context = get_context(alice(), 0);
+        context.attached_deposit = 3;
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        contract.configure_aggregator(vec![oracle(), bob(), "carol_near".to_string()], U64(2), AggregationMethod::Median);
+        assert_eq!(U64(1), contract.request_aggregated_price(eth_usd(), "dW5pcXVlIHNwZWMgaWQ=".to_string()));
+        contract
+    }
+
+    fn answer_as(contract: &mut ClientContract, oracle_account: AccountId, nonce: u128, answer: &str) {
+        let mut context = get_context(alice(), env::storage_usage());
+        context.predecessor_account_id = oracle_account;
+        context.block_timestamp = 42;
+        testing_env!(context);
+        contract.token_price_callback(U128(nonce), encode(answer), None);
+    }
+
+    #[test]
+    fn test_aggregated_round() {
+        let mut contract = aggregator_contract();
+        assert_eq!(vec![U128(1), U128(2), U128(3)], contract.get_outstanding_nonces());
+
+        answer_as(&mut contract, oracle(), 1, "1910");
+        assert_eq!(None, contract.get_latest_round().unwrap().answer);
+        answer_as(&mut contract, bob(), 2, "1930");
+        let round = contract.get_round(U64(1)).unwrap();
+        assert_eq!(Some(U128(1920)), round.answer);
+        assert_eq!(Some(U64(42)), round.updated_at);
+
+        answer_as(&mut contract, "carol_near".to_string(), 3, "1900");
+        let round = contract.get_latest_round().unwrap();
+        assert_eq!(Some(U128(1910)), round.answer);
+        assert_eq!(3, round.contributions.len());
+        assert_eq!(bob(), round.contributions[1].oracle);
+        assert_eq!(U128(1930), round.contributions[1].answer);
+    }
+
+    #[test]
+    fn test_aggregated_round_keeps_its_config() {
+        let mut contract = aggregator_contract();
+        answer_as(&mut contract, oracle(), 1, "1910");
+        // the next round needs all three answers, this one still aggregates after two
+        let mut context = get_context(alice(), env::storage_usage());
+        context.attached_deposit = 3;
+        testing_env!(context);
+        contract.configure_aggregator(vec![oracle(), bob(), "carol_near".to_string()], U64(3), AggregationMethod::Median);
+        answer_as(&mut contract, bob(), 2, "1930");
+        let round = contract.get_round(U64(1)).unwrap();
+        assert_eq!(Some(U128(1920)), round.answer);
+        assert_eq!(U64(2), round.min_responses);
+    }
+
+    #[test]
+    #[should_panic(expected = "At most 3 oracles can be configured.")]
+    fn test_configure_too_many_oracles() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        let oracles = (0..4).map(|index| format!("oracle-{}.testnet", index)).collect();
+        contract.configure_aggregator(oracles, U64(3), AggregationMethod::Median);
+    }
+
+    #[test]
+    #[should_panic(expected = "Trimming must leave at least one of the minimum responses.")]
+    fn test_configure_trim_overflow() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = ClientContract::new(oracle(), link());
+        contract.configure_aggregator(vec![oracle(), bob()], U64(2), AggregationMethod::TrimmedMean { trim: u64::MAX / 2 + 1 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the oracle can call this method.")]
+    fn test_aggregated_answer_from_other_oracle() {
+        let mut contract = aggregator_contract();
+        // nonce 2 was sent to bob
+        answer_as(&mut contract, oracle(), 2, "1910");
+    }
 }
@@ -0,0 +1,68 @@
+//! The `ft_mint` and `ft_burn` events of NEP-141, logged with the NEP-297 envelope of
+//! `oracle_types::events`, so indexers can follow changes of the total supply.
+use near_sdk::json_types::U128;
+use near_sdk::AccountId;
+use oracle_types::events::{self, Event, EventKind};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FtMintData {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FtBurnData {
+    pub owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// The event name and its data, tagged as `event` and `data` in the log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum TokenEventKind {
+    FtMint(Vec<FtMintData>),
+    FtBurn(Vec<FtBurnData>),
+}
+
+impl EventKind for TokenEventKind {
+    const STANDARD: &'static str = "nep141";
+    const VERSION: &'static str = "1.0.0";
+}
+
+pub type TokenEvent = Event<TokenEventKind>;
+
+/// Shorthand for creating and logging an event
+pub fn emit(kind: TokenEventKind) {
+    events::emit(kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mint_and_burn_round_trip() {
+        let event = TokenEvent::new(TokenEventKind::FtMint(vec![FtMintData {
+            owner_id: "client.testnet".to_string(),
+            amount: U128(100),
+            memo: None,
+        }]));
+        let log = event.to_log_string();
+        assert_eq!("EVENT_JSON:{\"standard\":\"nep141\",\"version\":\"1.0.0\",\"event\":\"ft_mint\",\"data\":[{\"owner_id\":\"client.testnet\",\"amount\":\"100\"}]}", log);
+        assert_eq!(Some(event), TokenEvent::from_log_string(&log));
+
+        let event = TokenEvent::new(TokenEventKind::FtBurn(vec![FtBurnData {
+            owner_id: "bridge.testnet".to_string(),
+            amount: U128(50),
+            memo: Some("unwrap".to_string()),
+        }]));
+        let log = event.to_log_string();
+        assert_eq!("EVENT_JSON:{\"standard\":\"nep141\",\"version\":\"1.0.0\",\"event\":\"ft_burn\",\"data\":[{\"owner_id\":\"bridge.testnet\",\"amount\":\"50\",\"memo\":\"unwrap\"}]}", log);
+        assert_eq!(Some(event), TokenEvent::from_log_string(&log));
+    }
+}
@@ -5,20 +5,27 @@
 *  - JSON calls should pass U128 as a base-10 string. E.g. "100".
 *  - The contract optimizes the inner trie structure by hashing account IDs. It will prevent some
 *    abuse of deep tries. Shouldn't be an issue, once NEAR clients implement full hashing of keys.
-*  - The contract tracks the change in storage before and after the call. If the storage increases,
-*    the contract requires the caller of the contract to attach enough deposit to the function call
-*    to cover the storage cost.
+*  - Accounts have to be registered with `storage_deposit` (NEP-145) before they can hold tokens.
+*    The deposit is kept as the account's storage balance, and the storage an account uses is
+*    charged against it. Allowances are charged to the balance owner.
 *    This is done to prevent a denial of service attack on the contract by taking all available storage.
-*    If the storage decreases, the contract will issue a refund for the cost of the released storage.
-*    The unused tokens from the attached deposit are also refunded, so it's safe to
-*    attach more deposit than required.
+*    Calls don't move NEAR around, so transfers are deterministic. Released storage becomes
+*    available again and can be taken out with `storage_withdraw`.
 *  - To prevent the deployed contract from being modified or deleted, it should not have any access
 *    keys on its account.
 */
 use borsh::{BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
-use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, Balance, Promise, StorageUsage};
+use near_sdk::collections::{UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, Promise, PromiseResult, StorageUsage};
+use oracle_types::interfaces::{ext_ft_receiver, ext_link};
+pub use oracle_types::interfaces::StorageBalance;
+use serde::{Deserialize, Serialize};
+
+pub mod events;
+pub mod metadata;
+use events::{FtBurnData, FtMintData, TokenEventKind};
+use metadata::{FungibleTokenMetadata, VersionedFungibleTokenMetadata};
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
@@ -26,28 +33,48 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 /// Price per 1 byte of storage from mainnet genesis config.
 const STORAGE_PRICE_PER_BYTE: Balance = 100000000000000000000;
 
+/// Gas reserved for `ft_resolve_transfer` after the receiver's `ft_on_transfer`.
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 10_000_000_000_000;
+
+/// Gas kept by `ft_transfer_call` itself, the rest is passed to the receiver.
+const GAS_FOR_FT_TRANSFER_CALL: Gas = 25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER;
+
+/// Allowance of one escrow account, stored with the plain Account ID so it can be listed.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Allowance {
+    pub escrow_account_id: AccountId,
+    pub amount: Balance,
+}
+
 /// Contains balance and allowances information for one account.
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Account {
+    /// Plain Account ID, stored next to its hash so holders can be listed.
+    pub account_id: AccountId,
     /// Current account balance.
     pub balance: Balance,
-    /// Escrow Account ID hash to the allowance amount.
+    /// Escrow Account ID hash to the allowance.
     /// Allowance is the amount of tokens the Escrow Account ID can spent on behalf of the account
     /// owner.
-    pub allowances: UnorderedMap<Vec<u8>, Balance>,
+    pub allowances: UnorderedMap<Vec<u8>, Allowance>,
+    /// NEAR deposited with `storage_deposit` to pay for the storage of this account.
+    pub storage_balance: Balance,
+    /// Bytes of storage charged to this account.
+    pub storage_usage: StorageUsage,
 }
 
 impl Account {
-    /// Initializes a new Account with 0 balance and no allowances for a given `account_hash`.
-    pub fn new(account_hash: Vec<u8>) -> Self {
-        Self { balance: 0, allowances: UnorderedMap::new(account_hash) }
+    /// Initializes a new Account with 0 balance and no allowances for a given `account_id` and its
+    /// `account_hash`.
+    pub fn new(account_id: AccountId, account_hash: Vec<u8>) -> Self {
+        Self { account_id, balance: 0, allowances: UnorderedMap::new(account_hash), storage_balance: 0, storage_usage: 0 }
     }
 
     /// Sets allowance for account `escrow_account_id` to `allowance`.
     pub fn set_allowance(&mut self, escrow_account_id: &AccountId, allowance: Balance) {
         let escrow_hash = env::sha256(escrow_account_id.as_bytes());
         if allowance > 0 {
-            self.allowances.insert(&escrow_hash, &allowance);
+            self.allowances.insert(&escrow_hash, &Allowance { escrow_account_id: escrow_account_id.clone(), amount: allowance });
         } else {
             self.allowances.remove(&escrow_hash);
         }
@@ -56,8 +83,32 @@ impl Account {
     /// Returns the allowance of account `escrow_account_id`.
     pub fn get_allowance(&self, escrow_account_id: &AccountId) -> Balance {
         let escrow_hash = env::sha256(escrow_account_id.as_bytes());
-        self.allowances.get(&escrow_hash).unwrap_or(0)
+        self.allowances.get(&escrow_hash).map(|allowance| allowance.amount).unwrap_or(0)
     }
+
+    /// Returns the part of the storage balance that isn't used to pay for storage.
+    pub fn available_storage_balance(&self) -> Balance {
+        self.storage_balance.saturating_sub(Balance::from(self.storage_usage) * STORAGE_PRICE_PER_BYTE)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct HolderJSON {
+    pub account_id: AccountId,
+    pub balance: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AllowanceJSON {
+    pub escrow_account_id: AccountId,
+    pub amount: U128,
+}
+
+/// NEP-145: Minimum and maximum storage balance of an account.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
 }
 
 #[near_bindgen]
@@ -68,6 +119,21 @@ pub struct FungibleToken {
 
     /// Total supply of the all token.
     pub total_supply: Balance,
+
+    /// Bytes used by a registered account without allowances.
+    pub account_storage_usage: StorageUsage,
+
+    /// Account that can update the metadata and manage minters.
+    pub owner_id: AccountId,
+
+    /// Accounts that can mint and burn tokens.
+    pub minters: UnorderedSet<AccountId>,
+
+    /// Hard cap of the total supply, if any.
+    pub max_supply: Option<Balance>,
+
+    /// NEP-148 metadata.
+    pub metadata: VersionedFungibleTokenMetadata,
 }
 
 impl Default for FungibleToken {
@@ -79,12 +145,29 @@ impl Default for FungibleToken {
 #[near_bindgen]
 impl FungibleToken {
     /// Initializes the contract with the given total supply owned by the given `owner_id`.
+    /// The owner is registered with the minimum storage balance, paid by the contract.
     #[init]
-    pub fn new(owner_id: AccountId, total_supply: U128) -> Self {
+    pub fn new(owner_id: AccountId, total_supply: U128, metadata: FungibleTokenMetadata, max_supply: Option<U128>) -> Self {
         let total_supply = total_supply.into();
+        let max_supply = max_supply.map(|max_supply| max_supply.0);
         assert!(!env::state_exists(), "Already initialized");
-        let mut ft = Self { accounts: UnorderedMap::new(b"a".to_vec()), total_supply };
-        let mut account = ft.get_account(&owner_id);
+        assert!(env::is_valid_account_id(owner_id.as_bytes()), "Owner's account ID is invalid");
+        metadata.assert_valid();
+        if let Some(max_supply) = max_supply {
+            assert!(total_supply <= max_supply, "Total supply can't be greater than the max supply");
+        }
+        let mut ft = Self {
+            accounts: UnorderedMap::new(b"a".to_vec()),
+            total_supply,
+            account_storage_usage: 0,
+            owner_id: owner_id.clone(),
+            minters: UnorderedSet::new(b"m".to_vec()),
+            max_supply,
+            metadata: metadata.into(),
+        };
+        ft.measure_account_storage_usage();
+        ft.internal_storage_deposit(&owner_id, ft.storage_balance_min());
+        let mut account = ft.get_registered_account(&owner_id);
         account.balance = total_supply;
         ft.set_account(&owner_id, &account);
         ft
@@ -93,43 +176,47 @@ impl FungibleToken {
     /// Increments the `allowance` for `escrow_account_id` by `amount` on the account of the caller of this contract
     /// (`predecessor_id`) who is the balance owner.
     /// Requirements:
-    /// * Caller of the method has to attach deposit enough to cover storage difference at the
-    ///   fixed storage price defined in the contract.
+    /// * Caller of the method has to have enough storage balance to cover storage difference at the
+    ///   fixed storage price defined in the contract. Any attached deposit is added to the caller's
+    ///   storage balance first.
     #[payable]
     pub fn inc_allowance(&mut self, escrow_account_id: AccountId, amount: U128) {
-        let initial_storage = env::storage_usage();
         assert!(
             env::is_valid_account_id(escrow_account_id.as_bytes()),
             "Escrow account ID is invalid"
         );
         let owner_id = env::predecessor_account_id();
         assert_ne!(escrow_account_id, owner_id, "Can not increment allowance for yourself");
-        let mut account = self.get_account(&owner_id);
+        self.deposit_attached_storage(&owner_id);
+        let initial_storage = env::storage_usage();
+        let mut account = self.get_registered_account(&owner_id);
         let current_allowance = account.get_allowance(&escrow_account_id);
         account.set_allowance(&escrow_account_id, current_allowance.saturating_add(amount.0));
         self.set_account(&owner_id, &account);
-        self.refund_storage(initial_storage);
+        self.charge_storage(&owner_id, initial_storage);
     }
 
     /// Decrements the `allowance` for `escrow_account_id` by `amount` on the account of the caller of this contract
     /// (`predecessor_id`) who is the balance owner.
     /// Requirements:
-    /// * Caller of the method has to attach deposit enough to cover storage difference at the
-    ///   fixed storage price defined in the contract.
+    /// * Caller of the method has to have enough storage balance to cover storage difference at the
+    ///   fixed storage price defined in the contract. Any attached deposit is added to the caller's
+    ///   storage balance first.
     #[payable]
     pub fn dec_allowance(&mut self, escrow_account_id: AccountId, amount: U128) {
-        let initial_storage = env::storage_usage();
         assert!(
             env::is_valid_account_id(escrow_account_id.as_bytes()),
             "Escrow account ID is invalid"
         );
         let owner_id = env::predecessor_account_id();
        assert_ne!(escrow_account_id, owner_id, "Can not decrement allowance for yourself");
-        let mut account = self.get_account(&owner_id);
+        self.deposit_attached_storage(&owner_id);
+        let initial_storage = env::storage_usage();
+        let mut account = self.get_registered_account(&owner_id);
         let current_allowance = account.get_allowance(&escrow_account_id);
         account.set_allowance(&escrow_account_id, current_allowance.saturating_sub(amount.0));
         self.set_account(&owner_id, &account);
-        self.refund_storage(initial_storage);
+        self.charge_storage(&owner_id, initial_storage);
     }
 
     /// Transfers the `amount` of tokens from `owner_id` to the `new_owner_id`.
@@ -139,11 +226,10 @@ impl FungibleToken {
     /// * If this function is called by an escrow account (`owner_id != predecessor_account_id`),
     ///   then the allowance of the caller of the function (`predecessor_account_id`) on
     ///   the account of `owner_id` should be greater or equal than the transfer `amount`.
-    /// * Caller of the method has to attach deposit enough to cover storage difference at the
-    ///   fixed storage price defined in the contract.
+    /// * `new_owner_id` has to be registered with `storage_deposit`.
+    /// * Any attached deposit is added to the caller's storage balance.
     #[payable]
     pub fn transfer_from(&mut self, owner_id: AccountId, new_owner_id: AccountId, amount: U128) {
-        let initial_storage = env::storage_usage();
         assert!(
             env::is_valid_account_id(new_owner_id.as_bytes()),
             "New owner's account ID is invalid"
@@ -156,8 +242,12 @@ impl FungibleToken {
             owner_id, new_owner_id,
             "The new owner should be different from the current owner"
         );
+        let escrow_account_id = env::predecessor_account_id();
+        self.deposit_attached_storage(&escrow_account_id);
+        let initial_storage = env::storage_usage();
+
         // Retrieving the account from the state.
-        let mut account = self.get_account(&owner_id);
+        let mut account = self.get_registered_account(&owner_id);
 
         // Checking and updating unlocked balance
         if account.balance < amount {
@@ -166,7 +256,6 @@ impl FungibleToken {
         account.balance -= amount;
 
         // If transferring by escrow, need to check and update allowance.
-        let escrow_account_id = env::predecessor_account_id();
         if escrow_account_id != owner_id {
             let allowance = account.get_allowance(&escrow_account_id);
             if allowance < amount {
@@ -179,26 +268,86 @@ impl FungibleToken {
         self.set_account(&owner_id, &account);
 
         // Deposit amount to the new owner and save the new account to the state.
-        let mut new_account = self.get_account(&new_owner_id);
+        let mut new_account = self.get_registered_account(&new_owner_id);
         new_account.balance += amount;
         self.set_account(&new_owner_id, &new_account);
-        self.refund_storage(initial_storage);
+        // Storage released by a used up allowance goes back to the balance owner.
+        self.charge_storage(&owner_id, initial_storage);
     }
 
     /// Transfer `amount` of tokens from the caller of the contract (`predecessor_id`) to
     /// `new_owner_id`.
     /// Act the same was as `transfer_from` with `owner_id` equal to the caller of the contract
     /// (`predecessor_id`).
-    /// Requirements:
-    /// * Caller of the method has to attach deposit enough to cover storage difference at the
-    ///   fixed storage price defined in the contract.
     #[payable]
     pub fn transfer(&mut self, new_owner_id: AccountId, amount: U128) {
         // NOTE: New owner's Account ID checked in transfer_from.
-        // Storage fees are also refunded in transfer_from.
+        // Attached deposit is also handled in transfer_from.
         self.transfer_from(env::predecessor_account_id(), new_owner_id, amount);
     }
 
+    /// NEP-141: Transfers `amount` of tokens from the caller of the contract (`predecessor_id`) to
+    /// `receiver_id`.
+    /// Requirements:
+    /// * Caller of the method has to attach exactly 1 yoctoNEAR.
+    /// * `receiver_id` has to be registered with `storage_deposit`.
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&sender_id, &receiver_id, amount.into(), memo);
+    }
+
+    /// NEP-141: Transfers `amount` of tokens to `receiver_id` and calls `ft_on_transfer` on it with
+    /// `msg`. The receiver returns the amount it didn't use, which is refunded to the caller in
+    /// `ft_resolve_transfer`.
+    /// Requirements:
+    /// * Caller of the method has to attach exactly 1 yoctoNEAR.
+    /// * `receiver_id` has to be registered with `storage_deposit`.
+    #[payable]
+    pub fn ft_transfer_call(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>, msg: String) -> Promise {
+        assert_one_yocto();
+        assert!(env::prepaid_gas() > GAS_FOR_FT_TRANSFER_CALL, "More gas is required");
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&sender_id, &receiver_id, amount.into(), memo);
+
+        let promise_on_transfer = ext_ft_receiver::ft_on_transfer(sender_id.clone(), amount, msg, &receiver_id, 0, env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL);
+
+        promise_on_transfer.then(ext_link::ft_resolve_transfer(sender_id, receiver_id, amount, &env::current_account_id(), 0, GAS_FOR_RESOLVE_TRANSFER))
+    }
+
+    /// NEP-141: Callback of `ft_transfer_call`. Refunds the amount the receiver didn't use, or the
+    /// whole amount if `ft_on_transfer` failed, and returns the amount that was used.
+    pub fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128 {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Method ft_resolve_transfer is private"
+        );
+        let amount: Balance = amount.into();
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => env::panic(b"The promise was not ready."),
+            PromiseResult::Successful(value) => {
+                match serde_json::from_slice::<U128>(&value) {
+                    Ok(unused_amount) => std::cmp::min(amount, unused_amount.0),
+                    Err(_) => amount,
+                }
+            }
+            PromiseResult::Failed => amount,
+        };
+        self.internal_resolve_transfer(&sender_id, &receiver_id, amount, unused_amount).into()
+    }
+
+    /// NEP-141: Returns total supply of tokens.
+    pub fn ft_total_supply(&self) -> U128 {
+        self.total_supply.into()
+    }
+
+    /// NEP-141: Returns balance of the `account_id` account.
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.get_account(&account_id).balance.into()
+    }
+
     /// Returns total supply of tokens.
     pub fn get_total_supply(&self) -> U128 {
         self.total_supply.into()
@@ -221,47 +370,377 @@ impl FungibleToken {
         );
         self.get_account(&owner_id).get_allowance(&escrow_account_id).into()
     }
+
+    /// Returns the accounts with a balance among `limit` registered accounts, starting at `from_index`.
+    /// Registered accounts with a zero balance are left out, so a page can be shorter than `limit`.
+    ///
+    /// NOTE: Pages are not stable. An account that unregisters is swapped with the last account,
+    /// so holders can be skipped or repeated when accounts unregister between calls.
+    pub fn get_holders(&self, from_index: U64, limit: U64) -> Vec<HolderJSON> {
+        let accounts = self.accounts.values_as_vector();
+        let from_index: u64 = from_index.into();
+        let limit: u64 = limit.into();
+        (from_index..std::cmp::min(from_index.saturating_add(limit), accounts.len()))
+            .filter_map(|index| accounts.get(index))
+            .filter(|account| account.balance > 0)
+            .map(|account| HolderJSON { account_id: account.account_id, balance: account.balance.into() })
+            .collect()
+    }
+
+    /// Returns all escrow accounts with an allowance on the account of `owner_id`.
+    ///
+    /// NOTE: Like `get_allowance`, this should only be used off-chain.
+    pub fn get_allowances(&self, owner_id: AccountId) -> Vec<AllowanceJSON> {
+        self.get_account(&owner_id)
+            .allowances
+            .values()
+            .map(|allowance| AllowanceJSON { escrow_account_id: allowance.escrow_account_id, amount: allowance.amount.into() })
+            .collect()
+    }
+
+    /// NEP-148: Returns the token metadata.
+    pub fn ft_metadata(&self) -> FungibleTokenMetadata {
+        self.metadata.latest()
+    }
+
+    /// Replaces the token metadata. Only the owner can call this method.
+    pub fn set_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        self.assert_owner();
+        metadata.assert_valid();
+        self.metadata = metadata.into();
+    }
+
+    /// Returns the account that can update the metadata and manage minters.
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    /// Allows `account_id` to mint and burn tokens. Only the owner can call this method.
+    pub fn add_minter(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        assert!(env::is_valid_account_id(account_id.as_bytes()), "Minter's account ID is invalid");
+        self.minters.insert(&account_id);
+    }
+
+    /// Removes the minter role from `account_id`. Only the owner can call this method.
+    pub fn remove_minter(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.minters.remove(&account_id);
+    }
+
+    pub fn is_minter(&self, account_id: AccountId) -> bool {
+        self.minters.contains(&account_id)
+    }
+
+    pub fn get_minters(&self) -> Vec<AccountId> {
+        self.minters.to_vec()
+    }
+
+    /// Returns the hard cap of the total supply, if there is one.
+    pub fn get_max_supply(&self) -> Option<U128> {
+        self.max_supply.map(|max_supply| max_supply.into())
+    }
+
+    /// Mints `amount` of new tokens to `account_id`, which has to be registered.
+    /// Only minters can call this method, and the total supply can't go over the max supply.
+    pub fn mint(&mut self, account_id: AccountId, amount: U128, memo: Option<String>) {
+        self.assert_minter();
+        let amount = amount.into();
+        if amount == 0 {
+            env::panic(b"Can't mint 0 tokens");
+        }
+        let total_supply = self.total_supply.checked_add(amount).expect("Total supply overflow");
+        if let Some(max_supply) = self.max_supply {
+            assert!(total_supply <= max_supply, "Minting would exceed the max supply of {}", max_supply);
+        }
+        let mut account = self.get_registered_account(&account_id);
+        account.balance += amount;
+        self.set_account(&account_id, &account);
+        self.total_supply = total_supply;
+        events::emit(TokenEventKind::FtMint(vec![FtMintData { owner_id: account_id, amount: amount.into(), memo }]));
+    }
+
+    /// Burns `amount` of tokens from the caller's balance. Only minters can call this method.
+    pub fn burn(&mut self, amount: U128, memo: Option<String>) {
+        self.assert_minter();
+        let amount = amount.into();
+        if amount == 0 {
+            env::panic(b"Can't burn 0 tokens");
+        }
+        let account_id = env::predecessor_account_id();
+        let mut account = self.get_registered_account(&account_id);
+        if account.balance < amount {
+            env::panic(b"Not enough balance");
+        }
+        account.balance -= amount;
+        self.set_account(&account_id, &account);
+        self.total_supply -= amount;
+        events::emit(TokenEventKind::FtBurn(vec![FtBurnData { owner_id: account_id, amount: amount.into(), memo }]));
+    }
+
+    /// NEP-145: Registers `account_id` (the caller by default) or adds the attached deposit to its
+    /// storage balance. With `registration_only` only the minimum balance is kept and the rest of
+    /// the deposit is refunded, including the whole deposit for accounts that are already registered.
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        assert!(env::is_valid_account_id(account_id.as_bytes()), "Account ID is invalid");
+        let refund = if registration_only.unwrap_or(false) {
+            if self.is_registered(&account_id) {
+                env::log(format!("The account {} is already registered", account_id).as_bytes());
+                amount
+            } else {
+                let min = self.storage_balance_min();
+                assert!(
+                    amount >= min,
+                    "The attached deposit is less than the minimum storage balance {}",
+                    min
+                );
+                self.internal_storage_deposit(&account_id, min);
+                amount - min
+            }
+        } else {
+            self.internal_storage_deposit(&account_id, amount);
+            0
+        };
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// NEP-145: Withdraws `amount` (all of it by default) of the caller's available storage balance.
+    /// Requires exactly 1 yoctoNEAR attached.
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.get_registered_account(&account_id);
+        let available = account.available_storage_balance();
+        let amount = amount.map(|a| a.0).unwrap_or(available);
+        assert!(
+            amount <= available,
+            "The amount is greater than the available storage balance {}",
+            available
+        );
+        if amount > 0 {
+            account.storage_balance -= amount;
+            self.set_account(&account_id, &account);
+            Promise::new(account_id.clone()).transfer(amount + 1);
+        }
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    /// NEP-145: Removes the caller's account and refunds its storage balance. Accounts that hold
+    /// tokens can only be removed with `force`, which burns the tokens.
+    /// Requires exactly 1 yoctoNEAR attached. Returns `false` if the caller isn't registered.
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let account_hash = env::sha256(account_id.as_bytes());
+        let mut account = match self.accounts.get(&account_hash) {
+            Some(account) => account,
+            None => {
+                env::log(format!("The account {} is not registered", account_id).as_bytes());
+                return false;
+            }
+        };
+        if account.balance > 0 {
+            assert!(
+                force.unwrap_or(false),
+                "Can't unregister the account with the positive balance without force"
+            );
+            self.total_supply -= account.balance;
+            events::emit(TokenEventKind::FtBurn(vec![FtBurnData {
+                owner_id: account_id.clone(),
+                amount: account.balance.into(),
+                memo: Some("Unregistered with force".to_string()),
+            }]));
+        }
+        account.allowances.clear();
+        self.accounts.remove(&account_hash);
+        Promise::new(account_id).transfer(account.storage_balance + 1);
+        true
+    }
+
+    /// NEP-145: Returns the storage balance of `account_id`, or `None` if it isn't registered.
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        let account_hash = env::sha256(account_id.as_bytes());
+        self.accounts.get(&account_hash).map(|account| StorageBalance {
+            total: account.storage_balance.into(),
+            available: account.available_storage_balance().into(),
+        })
+    }
+
+    /// NEP-145: Returns the minimum storage balance needed to register an account. There is no
+    /// maximum, since allowances are charged to the storage balance too.
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds { min: self.storage_balance_min().into(), max: None }
+    }
+}
+
+/// Asserts that exactly 1 yoctoNEAR is attached, which requires a full access key.
+fn assert_one_yocto() {
+    assert_eq!(env::attached_deposit(), 1, "Requires attached deposit of exactly 1 yoctoNEAR");
 }
 
 impl FungibleToken {
+    fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Only the owner can call this method");
+    }
+
+    fn assert_minter(&self) {
+        assert!(self.minters.contains(&env::predecessor_account_id()), "Only minters can call this method");
+    }
+
     /// Helper method to get the account details for `owner_id`.
     fn get_account(&self, owner_id: &AccountId) -> Account {
         assert!(env::is_valid_account_id(owner_id.as_bytes()), "Owner's account ID is invalid");
         let account_hash = env::sha256(owner_id.as_bytes());
-        self.accounts.get(&account_hash).unwrap_or_else(|| Account::new(account_hash))
+        self.accounts.get(&account_hash).unwrap_or_else(|| Account::new(owner_id.clone(), account_hash))
+    }
+
+    /// Helper method to get the account details for `owner_id`, which has to be registered.
+    fn get_registered_account(&self, owner_id: &AccountId) -> Account {
+        assert!(env::is_valid_account_id(owner_id.as_bytes()), "Owner's account ID is invalid");
+        let account_hash = env::sha256(owner_id.as_bytes());
+        match self.accounts.get(&account_hash) {
+            Some(account) => account,
+            None => env::panic(format!("The account {} is not registered", owner_id).as_bytes()),
+        }
+    }
+
+    fn is_registered(&self, owner_id: &AccountId) -> bool {
+        self.accounts.get(&env::sha256(owner_id.as_bytes())).is_some()
     }
 
     /// Helper method to set the account details for `owner_id` to the state.
     fn set_account(&mut self, owner_id: &AccountId, account: &Account) {
         let account_hash = env::sha256(owner_id.as_bytes());
-        if account.balance > 0 || !account.allowances.is_empty() {
-            self.accounts.insert(&account_hash, &account);
-        } else {
-            self.accounts.remove(&account_hash);
+        self.accounts.insert(&account_hash, &account);
+    }
+
+    /// Measures the storage used by one registered account without allowances.
+    fn measure_account_storage_usage(&mut self) {
+        let initial_storage = env::storage_usage();
+        let tmp_account_id = "a".repeat(64);
+        let tmp_account_hash = env::sha256(tmp_account_id.as_bytes());
+        self.accounts.insert(&tmp_account_hash, &Account::new(tmp_account_id, tmp_account_hash.clone()));
+        self.account_storage_usage = env::storage_usage() - initial_storage;
+        self.accounts.remove(&tmp_account_hash);
+    }
+
+    fn storage_balance_min(&self) -> Balance {
+        Balance::from(self.account_storage_usage) * STORAGE_PRICE_PER_BYTE
+    }
+
+    /// Adds `amount` to the storage balance of `account_id`, registering the account if needed.
+    fn internal_storage_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        let account_hash = env::sha256(account_id.as_bytes());
+        let mut account = match self.accounts.get(&account_hash) {
+            Some(account) => account,
+            None => {
+                let min = self.storage_balance_min();
+                assert!(
+                    amount >= min,
+                    "The attached deposit is less than the minimum storage balance {}",
+                    min
+                );
+                let mut account = Account::new(account_id.clone(), account_hash);
+                account.storage_usage = self.account_storage_usage;
+                account
+            }
+        };
+        account.storage_balance += amount;
+        self.set_account(account_id, &account);
+    }
+
+    /// Adds the attached deposit of a NEP-21 call to the storage balance of `account_id`.
+    fn deposit_attached_storage(&mut self, account_id: &AccountId) {
+        let attached_deposit = env::attached_deposit();
+        if attached_deposit > 0 {
+            self.internal_storage_deposit(account_id, attached_deposit);
         }
     }
 
-    fn refund_storage(&self, initial_storage: StorageUsage) {
+    /// Charges the storage difference since `initial_storage` to the storage balance of
+    /// `account_id`. Released storage is credited back.
+    fn charge_storage(&mut self, account_id: &AccountId, initial_storage: StorageUsage) {
         let current_storage = env::storage_usage();
-        let attached_deposit = env::attached_deposit();
-        let refund_amount = if current_storage > initial_storage {
-            let required_deposit =
-                Balance::from(current_storage - initial_storage) * STORAGE_PRICE_PER_BYTE;
-            assert!(
-                required_deposit <= attached_deposit,
-                "The required attached deposit is {}, but the given attached deposit is is {}",
-                required_deposit,
-                attached_deposit,
-            );
-            attached_deposit - required_deposit
+        let mut account = self.get_registered_account(account_id);
+        if current_storage > initial_storage {
+            account.storage_usage += current_storage - initial_storage;
         } else {
-            attached_deposit
-                + Balance::from(initial_storage - current_storage) * STORAGE_PRICE_PER_BYTE
-        };
-        if refund_amount > 0 {
-            env::log(format!("Refunding {} tokens for storage", refund_amount).as_bytes());
-            Promise::new(env::predecessor_account_id()).transfer(refund_amount);
+            account.storage_usage = account.storage_usage.saturating_sub(initial_storage - current_storage);
+        }
+        let required_balance = Balance::from(account.storage_usage) * STORAGE_PRICE_PER_BYTE;
+        assert!(
+            required_balance <= account.storage_balance,
+            "Not enough storage balance, the required storage balance is {}, but the storage balance is {}",
+            required_balance,
+            account.storage_balance,
+        );
+        self.set_account(account_id, &account);
+    }
+
+    /// Moves `amount` of tokens from `sender_id` to `receiver_id` without checking allowances.
+    fn internal_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: Balance, memo: Option<String>) {
+        assert!(
+            env::is_valid_account_id(receiver_id.as_bytes()),
+            "Receiver's account ID is invalid"
+        );
+        if amount == 0 {
+            env::panic(b"Can't transfer 0 tokens");
         }
+        assert_ne!(sender_id, receiver_id, "The receiver should be different from the sender");
+        let mut sender = self.get_registered_account(sender_id);
+        if sender.balance < amount {
+            env::panic(b"Not enough balance");
+        }
+        sender.balance -= amount;
+        self.set_account(sender_id, &sender);
+
+        let mut receiver = self.get_registered_account(receiver_id);
+        receiver.balance += amount;
+        self.set_account(receiver_id, &receiver);
+
+        env::log(format!("Transfer {} from {} to {}", amount, sender_id, receiver_id).as_bytes());
+        if let Some(memo) = memo {
+            env::log(format!("Memo: {}", memo).as_bytes());
+        }
+    }
+
+    /// Refunds up to `unused_amount` of an `ft_transfer_call` back to `sender_id`, limited by what
+    /// `receiver_id` still holds. Returns the amount that stays with the receiver.
+    fn internal_resolve_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: Balance, unused_amount: Balance) -> Balance {
+        if unused_amount == 0 {
+            return amount;
+        }
+        let mut receiver = self.get_account(receiver_id);
+        let refund_amount = std::cmp::min(receiver.balance, unused_amount);
+        if refund_amount == 0 {
+            return amount;
+        }
+        receiver.balance -= refund_amount;
+        self.set_account(receiver_id, &receiver);
+
+        // The sender could have unregistered in the meantime, in which case the refund is burned.
+        if self.is_registered(sender_id) {
+            let mut sender = self.get_registered_account(sender_id);
+            sender.balance += refund_amount;
+            self.set_account(sender_id, &sender);
+            env::log(format!("Refund {} from {} to {}", refund_amount, receiver_id, sender_id).as_bytes());
+        } else {
+            self.total_supply -= refund_amount;
+            events::emit(TokenEventKind::FtBurn(vec![FtBurnData {
+                owner_id: receiver_id.clone(),
+                amount: refund_amount.into(),
+                memo: Some(format!("Refund to unregistered {}", sender_id)),
+            }]));
+        }
+        amount - refund_amount
     }
 }
 
@@ -304,12 +783,71 @@ mod tests {
         }
     }
 
+    fn link_metadata() -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: "ChainLink Token".to_string(),
+            symbol: "LINK".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 18,
+        }
+    }
+
+    fn assert_supply_consistent(contract: &FungibleToken) {
+        let sum: Balance = contract.accounts.values().map(|account| account.balance).sum();
+        assert_eq!(sum, contract.ft_total_supply().0);
+    }
+
+    fn register(contract: &mut FungibleToken, account_id: AccountId) {
+        let mut context = get_context(account_id);
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 1000 * STORAGE_PRICE_PER_BYTE;
+        testing_env!(context);
+        contract.storage_deposit(None, None);
+    }
+
+    /// Compile-time check that `ext_link` in oracle-types takes the arguments of the token's methods,
+    /// and that those return what it declares
+    #[test]
+    fn ext_link_matches_contract() {
+        let _get_allowance = |contract: &FungibleToken, owner_id: AccountId, escrow_account_id: AccountId| -> U128 {
+            ext_link::get_allowance(owner_id.clone(), escrow_account_id.clone(), &alice(), 0, 0);
+            contract.get_allowance(owner_id, escrow_account_id)
+        };
+        let _inc_allowance = |contract: &mut FungibleToken, escrow_account_id: AccountId, amount: U128| {
+            ext_link::inc_allowance(escrow_account_id.clone(), amount, &alice(), 0, 0);
+            contract.inc_allowance(escrow_account_id, amount)
+        };
+        let _transfer_from = |contract: &mut FungibleToken, owner_id: AccountId, new_owner_id: AccountId, amount: U128| {
+            ext_link::transfer_from(owner_id.clone(), new_owner_id.clone(), amount, &alice(), 0, 0);
+            contract.transfer_from(owner_id, new_owner_id, amount)
+        };
+        let _transfer = |contract: &mut FungibleToken, new_owner_id: AccountId, amount: U128| {
+            ext_link::transfer(new_owner_id.clone(), amount, &alice(), 0, 0);
+            contract.transfer(new_owner_id, amount)
+        };
+        let _ft_transfer_call = |contract: &mut FungibleToken, receiver_id: AccountId, amount: U128, memo: Option<String>, msg: String| -> Promise {
+            ext_link::ft_transfer_call(receiver_id.clone(), amount, memo.clone(), msg.clone(), &alice(), 0, 0);
+            contract.ft_transfer_call(receiver_id, amount, memo, msg)
+        };
+        let _ft_resolve_transfer = |contract: &mut FungibleToken, sender_id: AccountId, receiver_id: AccountId, amount: U128| -> U128 {
+            ext_link::ft_resolve_transfer(sender_id.clone(), receiver_id.clone(), amount, &alice(), 0, 0);
+            contract.ft_resolve_transfer(sender_id, receiver_id, amount)
+        };
+        let _storage_withdraw = |contract: &mut FungibleToken, amount: Option<U128>| -> StorageBalance {
+            ext_link::storage_withdraw(amount, &alice(), 0, 0);
+            contract.storage_withdraw(amount)
+        };
+    }
+
     #[test]
     fn test_new() {
         let context = get_context(carol());
         testing_env!(context);
         let total_supply = 1_000_000_000_000_000u128;
-        let contract = FungibleToken::new(bob(), total_supply.into());
+        let contract = FungibleToken::new(bob(), total_supply.into(), link_metadata(), None);
         assert_eq!(contract.get_total_supply().0, total_supply);
         assert_eq!(contract.get_balance(bob()).0, total_supply);
     }
@@ -321,9 +859,9 @@ mod tests {
         testing_env!(context);
         let total_supply = 1_000_000_000_000_000u128;
         {
-            let _contract = FungibleToken::new(bob(), total_supply.into());
+            let _contract = FungibleToken::new(bob(), total_supply.into(), link_metadata(), None);
         }
-        FungibleToken::new(bob(), total_supply.into());
+        FungibleToken::new(bob(), total_supply.into(), link_metadata(), None);
     }
 
     #[test]
@@ -331,7 +869,8 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, bob());
         context.storage_usage = env::storage_usage();
 
         context.attached_deposit = 1000 * STORAGE_PRICE_PER_BYTE;
@@ -354,7 +893,7 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.storage_usage = env::storage_usage();
 
         context.attached_deposit = 1000 * STORAGE_PRICE_PER_BYTE;
@@ -369,7 +908,7 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.attached_deposit = STORAGE_PRICE_PER_BYTE * 1000;
         testing_env!(context.clone());
         contract.inc_allowance(carol(), (total_supply / 2).into());
@@ -381,7 +920,7 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.attached_deposit = STORAGE_PRICE_PER_BYTE * 1000;
         testing_env!(context.clone());
         contract.dec_allowance(carol(), (total_supply / 2).into());
@@ -392,7 +931,7 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.attached_deposit = STORAGE_PRICE_PER_BYTE * 1000;
         testing_env!(context.clone());
         contract.dec_allowance(bob(), (total_supply / 2).into());
@@ -404,7 +943,7 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = std::u128::MAX;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.attached_deposit = STORAGE_PRICE_PER_BYTE * 1000;
         testing_env!(context.clone());
         contract.inc_allowance(bob(), total_supply.into());
@@ -413,14 +952,12 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(
-        expected = "The required attached deposit is 33100000000000000000000, but the given attached deposit is is 0"
-    )]
+    #[should_panic(expected = "Not enough storage balance")]
     fn test_self_allowance_fail_no_deposit() {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.attached_deposit = 0;
         testing_env!(context.clone());
         contract.inc_allowance(bob(), (total_supply / 2).into());
@@ -432,7 +969,8 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, alice());
         context.storage_usage = env::storage_usage();
 
         context.is_view = true;
@@ -476,7 +1014,8 @@ mod tests {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, alice());
         context.storage_usage = env::storage_usage();
 
         context.is_view = true;
@@ -516,40 +1055,354 @@ mod tests {
     }
 
     #[test]
-    fn test_self_allowance_set_for_refund() {
+    fn test_allowance_storage_is_charged_to_owner() {
         let mut context = get_context(carol());
         testing_env!(context.clone());
         let total_supply = 1_000_000_000_000_000u128;
-        let mut contract = FungibleToken::new(carol(), total_supply.into());
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
         context.storage_usage = env::storage_usage();
+        let min = contract.storage_balance_bounds().min.0;
+        assert_eq!(contract.storage_balance_of(carol()), Some(StorageBalance { total: min.into(), available: 0.into() }));
 
-        let initial_balance = context.account_balance;
         let initial_storage = context.storage_usage;
         context.attached_deposit = STORAGE_PRICE_PER_BYTE * 1000;
         testing_env!(context.clone());
         contract.inc_allowance(bob(), (total_supply / 2).into());
         context.storage_usage = env::storage_usage();
-        context.account_balance = env::account_balance();
-        assert_eq!(
-            context.account_balance,
-            initial_balance
-                + Balance::from(context.storage_usage - initial_storage) * STORAGE_PRICE_PER_BYTE
-        );
+        let allowance_storage = Balance::from(context.storage_usage - initial_storage) * STORAGE_PRICE_PER_BYTE;
+        assert!(allowance_storage > 0);
+        let storage_balance = contract.storage_balance_of(carol()).unwrap();
+        assert_eq!(storage_balance.total.0, min + STORAGE_PRICE_PER_BYTE * 1000);
+        assert_eq!(storage_balance.available.0, STORAGE_PRICE_PER_BYTE * 1000 - allowance_storage);
 
-        let initial_balance = context.account_balance;
-        let initial_storage = context.storage_usage;
-        testing_env!(context.clone());
         context.attached_deposit = 0;
         testing_env!(context.clone());
         contract.dec_allowance(bob(), (total_supply / 2).into());
+        let storage_balance = contract.storage_balance_of(carol()).unwrap();
+        assert_eq!(storage_balance.available.0, STORAGE_PRICE_PER_BYTE * 1000);
+    }
+
+    #[test]
+    fn test_storage_deposit() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        assert_eq!(contract.storage_balance_of(bob()), None);
+        let min = contract.storage_balance_bounds().min.0;
+        assert!(min > 0);
+        assert_eq!(contract.storage_balance_bounds().max, None);
+
+        let mut context = get_context(carol());
         context.storage_usage = env::storage_usage();
-        context.account_balance = env::account_balance();
-        assert!(context.storage_usage < initial_storage);
-        assert!(context.account_balance < initial_balance);
-        assert_eq!(
-            context.account_balance,
-            initial_balance
-                - Balance::from(initial_storage - context.storage_usage) * STORAGE_PRICE_PER_BYTE
-        );
+        context.attached_deposit = min * 2;
+        testing_env!(context);
+        let storage_balance = contract.storage_deposit(Some(bob()), Some(true));
+        assert_eq!(storage_balance, StorageBalance { total: min.into(), available: 0.into() });
+
+        // Already registered, the deposit is refunded
+        let storage_balance = contract.storage_deposit(Some(bob()), Some(true));
+        assert_eq!(storage_balance.total.0, min);
+
+        let storage_balance = contract.storage_deposit(Some(bob()), None);
+        assert_eq!(storage_balance, StorageBalance { total: (min * 3).into(), available: (min * 2).into() });
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_storage_deposit_below_minimum() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = contract.storage_balance_bounds().min.0 - 1;
+        testing_env!(context);
+        contract.storage_deposit(Some(bob()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_storage_deposit_registration_only_below_minimum() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = contract.storage_balance_bounds().min.0 - 1;
+        testing_env!(context);
+        contract.storage_deposit(Some(bob()), Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "The account bob.near is not registered")]
+    fn test_transfer_to_unregistered_fails() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 1;
+        testing_env!(context);
+        contract.ft_transfer(bob(), 10.into(), None);
+    }
+
+    #[test]
+    fn test_storage_withdraw() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, bob());
+        let min = contract.storage_balance_bounds().min.0;
+
+        let mut context = get_context(bob());
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 1;
+        testing_env!(context);
+        let storage_balance = contract.storage_withdraw(Some(10.into()));
+        assert_eq!(storage_balance.total.0, 1000 * STORAGE_PRICE_PER_BYTE - 10);
+        let storage_balance = contract.storage_withdraw(None);
+        assert_eq!(storage_balance, StorageBalance { total: min.into(), available: 0.into() });
+    }
+
+    #[test]
+    fn test_storage_unregister() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, bob());
+
+        let mut context = get_context(carol());
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 1;
+        testing_env!(context.clone());
+        contract.ft_transfer(bob(), 100.into(), None);
+
+        context.predecessor_account_id = bob();
+        testing_env!(context);
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.storage_balance_of(bob()), None);
+        assert_eq!(contract.ft_balance_of(bob()).0, 0);
+        assert_eq!(contract.ft_total_supply().0, total_supply - 100);
+        assert_supply_consistent(&contract);
+        assert!(!contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister the account with the positive balance without force")]
+    fn test_storage_unregister_with_balance_fails() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 1;
+        testing_env!(context);
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_ft_transfer() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, bob());
+        context.storage_usage = env::storage_usage();
+
+        context.attached_deposit = 1;
+        testing_env!(context.clone());
+        let transfer_amount = total_supply / 3;
+        contract.ft_transfer(bob(), transfer_amount.into(), Some("for the oracle".to_string()));
+        assert_eq!(contract.ft_balance_of(carol()).0, total_supply - transfer_amount);
+        assert_eq!(contract.ft_balance_of(bob()).0, transfer_amount);
+        assert_eq!(contract.ft_total_supply().0, total_supply);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn test_ft_transfer_call_no_deposit() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        contract.ft_transfer_call(bob(), 10.into(), None, "".to_string());
+    }
+
+    #[test]
+    fn test_ft_transfer_call_refunds_unused() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let total_supply = 1_000_000_000_000_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, bob());
+        context.storage_usage = env::storage_usage();
+
+        context.attached_deposit = 1;
+        testing_env!(context.clone());
+        contract.ft_transfer_call(bob(), 100.into(), None, "{}".to_string());
+        assert_eq!(contract.ft_balance_of(bob()).0, 100);
+
+        // Bob used 60 of the 100 tokens
+        let used = contract.internal_resolve_transfer(&carol(), &bob(), 100, 40);
+        assert_eq!(used, 60);
+        assert_eq!(contract.ft_balance_of(carol()).0, total_supply - 60);
+        assert_eq!(contract.ft_balance_of(bob()).0, 60);
+
+        // The refund is limited by what the receiver still holds
+        let used = contract.internal_resolve_transfer(&carol(), &bob(), 100, 100);
+        assert_eq!(used, 40);
+        assert_eq!(contract.ft_balance_of(carol()).0, total_supply);
+        assert_eq!(contract.ft_balance_of(bob()).0, 0);
+        assert_supply_consistent(&contract);
+    }
+
+    #[test]
+    fn test_ft_metadata() {
+        let mut context = get_context(carol());
+        testing_env!(context.clone());
+        let mut contract = FungibleToken::new(carol(), 1_000u128.into(), link_metadata(), None);
+        assert_eq!(contract.ft_metadata(), link_metadata());
+
+        let mut metadata = link_metadata();
+        metadata.reference = Some("https://example.com/link.json".to_string());
+        metadata.reference_hash = Some(base64::encode(&[1u8; 32]));
+        context.storage_usage = env::storage_usage();
+        testing_env!(context);
+        contract.set_metadata(metadata.clone());
+        assert_eq!(contract.ft_metadata(), metadata);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_set_metadata_not_owner() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let mut contract = FungibleToken::new(carol(), 1_000u128.into(), link_metadata(), None);
+        testing_env!(get_context(bob()));
+        contract.set_metadata(link_metadata());
+    }
+
+    #[test]
+    #[should_panic(expected = "Metadata decimals should be at most 38")]
+    fn test_metadata_decimals_out_of_range() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let mut metadata = link_metadata();
+        metadata.decimals = 39;
+        FungibleToken::new(carol(), 1_000u128.into(), metadata, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Metadata reference_hash should be 32 bytes")]
+    fn test_metadata_reference_hash_length() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let mut contract = FungibleToken::new(carol(), 1_000u128.into(), link_metadata(), None);
+        let mut metadata = link_metadata();
+        metadata.reference = Some("https://example.com/link.json".to_string());
+        metadata.reference_hash = Some(base64::encode(&[1u8; 16]));
+        contract.set_metadata(metadata);
+    }
+
+    #[test]
+    fn test_mint_and_burn() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = 1_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), Some(2_000u128.into()));
+        register(&mut contract, bob());
+        register(&mut contract, alice());
+
+        let mut context = get_context(carol());
+        context.storage_usage = env::storage_usage();
+        testing_env!(context.clone());
+        contract.add_minter(bob());
+        assert!(contract.is_minter(bob()));
+        assert_eq!(contract.get_minters(), vec![bob()]);
+
+        context.predecessor_account_id = bob();
+        testing_env!(context.clone());
+        contract.mint(bob(), 600.into(), None);
+        contract.mint(alice(), 400.into(), Some("bridged".to_string()));
+        assert_eq!(contract.ft_total_supply().0, 2_000);
+        assert_eq!(contract.ft_balance_of(alice()).0, 400);
+        assert_supply_consistent(&contract);
+
+        contract.burn(250.into(), None);
+        assert_eq!(contract.ft_total_supply().0, 1_750);
+        assert_eq!(contract.ft_balance_of(bob()).0, 350);
+        assert_supply_consistent(&contract);
+
+        // Burned tokens can be minted again up to the cap
+        contract.mint(alice(), 250.into(), None);
+        assert_eq!(contract.get_max_supply(), Some(contract.ft_total_supply()));
+        assert_supply_consistent(&contract);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting would exceed the max supply of 2000")]
+    fn test_mint_over_max_supply() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let mut contract = FungibleToken::new(carol(), 1_000u128.into(), link_metadata(), Some(2_000u128.into()));
+        contract.add_minter(carol());
+        contract.mint(carol(), 1_001.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only minters can call this method")]
+    fn test_mint_not_minter() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let mut contract = FungibleToken::new(carol(), 1_000u128.into(), link_metadata(), None);
+        contract.mint(carol(), 1.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_add_minter_not_owner() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let mut contract = FungibleToken::new(carol(), 1_000u128.into(), link_metadata(), None);
+        testing_env!(get_context(bob()));
+        contract.add_minter(bob());
+    }
+
+    #[test]
+    fn test_get_holders_and_allowances() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = 1_000u128;
+        let mut contract = FungibleToken::new(carol(), total_supply.into(), link_metadata(), None);
+        register(&mut contract, bob());
+        register(&mut contract, alice());
+
+        let mut context = get_context(carol());
+        context.storage_usage = env::storage_usage();
+        context.attached_deposit = 1;
+        testing_env!(context.clone());
+        contract.ft_transfer(bob(), 100.into(), None);
+        context.attached_deposit = 1000 * STORAGE_PRICE_PER_BYTE;
+        testing_env!(context);
+        contract.inc_allowance(bob(), 30.into());
+        contract.inc_allowance(alice(), 20.into());
+
+        let holders = contract.get_holders(0.into(), 10.into());
+        assert_eq!(holders, vec![
+            HolderJSON { account_id: carol(), balance: 900.into() },
+            HolderJSON { account_id: bob(), balance: 100.into() },
+        ]);
+        assert_eq!(contract.get_holders(1.into(), 1.into()), vec![HolderJSON { account_id: bob(), balance: 100.into() }]);
+        assert!(contract.get_holders(2.into(), 10.into()).is_empty());
+        assert!(contract.get_holders(3.into(), 10.into()).is_empty());
+
+        assert_eq!(contract.get_allowances(carol()), vec![
+            AllowanceJSON { escrow_account_id: bob(), amount: 30.into() },
+            AllowanceJSON { escrow_account_id: alice(), amount: 20.into() },
+        ]);
+        assert!(contract.get_allowances(bob()).is_empty());
     }
 }
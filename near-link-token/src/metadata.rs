@@ -0,0 +1,66 @@
+//! NEP-148 fungible token metadata.
+//! The metadata is stored as a versioned enum, so new fields can be added in a later version
+//! without migrating the state by hand.
+use borsh::{BorshDeserialize, BorshSerialize};
+use oracle_types::Base64String;
+use serde::{Deserialize, Serialize};
+
+pub const FT_METADATA_SPEC: &str = "ft-1.0.0";
+
+/// A u128 balance has at most 39 digits, so more decimals can't be displayed.
+pub const MAX_DECIMALS: u8 = 38;
+
+/// Length of the sha256 hash of the file behind `reference`.
+const REFERENCE_HASH_LEN: usize = 32;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FungibleTokenMetadata {
+    pub spec: String,
+    pub name: String,
+    pub symbol: String,
+    /// Data URL of the icon, e.g. an SVG.
+    pub icon: Option<String>,
+    /// Link to a JSON file with more information about the token.
+    pub reference: Option<String>,
+    /// Base64 encoded sha256 hash of the JSON file behind `reference`.
+    pub reference_hash: Option<Base64String>,
+    pub decimals: u8,
+}
+
+impl FungibleTokenMetadata {
+    /// Panics if the metadata can't be stored.
+    pub fn assert_valid(&self) {
+        assert_eq!(self.spec, FT_METADATA_SPEC, "Metadata spec should be {}", FT_METADATA_SPEC);
+        assert!(!self.name.is_empty(), "Metadata name can't be empty");
+        assert!(!self.symbol.is_empty(), "Metadata symbol can't be empty");
+        assert!(self.decimals <= MAX_DECIMALS, "Metadata decimals should be at most {}", MAX_DECIMALS);
+        assert_eq!(
+            self.reference.is_some(),
+            self.reference_hash.is_some(),
+            "Metadata reference and reference_hash should be set together"
+        );
+        if let Some(reference_hash) = &self.reference_hash {
+            let hash = base64::decode(reference_hash).expect("Metadata reference_hash isn't valid base64");
+            assert_eq!(hash.len(), REFERENCE_HASH_LEN, "Metadata reference_hash should be {} bytes", REFERENCE_HASH_LEN);
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum VersionedFungibleTokenMetadata {
+    V1(FungibleTokenMetadata),
+}
+
+impl VersionedFungibleTokenMetadata {
+    pub fn latest(&self) -> FungibleTokenMetadata {
+        match self {
+            VersionedFungibleTokenMetadata::V1(metadata) => metadata.clone(),
+        }
+    }
+}
+
+impl From<FungibleTokenMetadata> for VersionedFungibleTokenMetadata {
+    fn from(metadata: FungibleTokenMetadata) -> Self {
+        VersionedFungibleTokenMetadata::V1(metadata)
+    }
+}
@@ -0,0 +1,187 @@
+//! Typed answers.
+//! A node can fulfill a request with a list of typed words instead of one opaque string. Job specs
+//! can declare the types they answer with, which the oracle checks before calling back. The
+//! consumer's `answer` is then the Base64 encoded JSON of the words, e.g.
+//! `[{"type": "Int256", "value": "-1910"}, {"type": "Bool", "value": true}]`
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::Base64String;
+
+/// Largest absolute value of a positive and a negative int256, 2^255 - 1 and 2^255
+const INT256_MAX: &str = "57896044618658097711785492504343953926634992332820282019728792003956564819967";
+const INT256_MIN_ABS: &str = "57896044618658097711785492504343953926634992332820282019728792003956564819968";
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AnswerType {
+    Int256,
+    Bytes,
+    Bool,
+    String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value")]
+pub enum AnswerWord {
+    /// Signed integer in decimal, without leading zeros
+    Int256(String),
+    Bytes(Base64String),
+    Bool(bool),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnswerError {
+    WrongLength { expected: usize, found: usize },
+    WrongType { index: usize, expected: AnswerType },
+    InvalidInt256(usize),
+    InvalidBytes(usize),
+    InvalidBase64,
+    InvalidUtf8,
+    InvalidJson(String),
+}
+
+impl fmt::Display for AnswerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnswerError::WrongLength { expected, found } => write!(f, "Expected {} answer words, found {}", expected, found),
+            AnswerError::WrongType { index, expected } => write!(f, "Answer word {} must be {:?}", index, expected),
+            AnswerError::InvalidInt256(index) => write!(f, "Answer word {} is not a valid int256", index),
+            AnswerError::InvalidBytes(index) => write!(f, "Answer word {} is not valid Base64", index),
+            AnswerError::InvalidBase64 => write!(f, "Answer is not valid Base64"),
+            AnswerError::InvalidUtf8 => write!(f, "Answer is not valid UTF-8"),
+            AnswerError::InvalidJson(error) => write!(f, "Answer is not valid JSON: {}", error),
+        }
+    }
+}
+
+impl AnswerWord {
+    pub fn answer_type(&self) -> AnswerType {
+        match self {
+            AnswerWord::Int256(_) => AnswerType::Int256,
+            AnswerWord::Bytes(_) => AnswerType::Bytes,
+            AnswerWord::Bool(_) => AnswerType::Bool,
+            AnswerWord::String(_) => AnswerType::String,
+        }
+    }
+
+    /// Checks the value of the word at `index` fits its type
+    pub fn validate(&self, index: usize) -> Result<(), AnswerError> {
+        match self {
+            AnswerWord::Int256(value) if !is_int256(value) => Err(AnswerError::InvalidInt256(index)),
+            AnswerWord::Bytes(value) if base64::decode(value).is_err() => Err(AnswerError::InvalidBytes(index)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_int256(value: &str) -> bool {
+    let (digits, max) = match value.strip_prefix('-') {
+        Some(digits) => (digits, INT256_MIN_ABS),
+        None => (value, INT256_MAX),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    // no leading zeros and no negative zero, so every integer has one representation
+    if digits.starts_with('0') && (digits.len() > 1 || value.starts_with('-')) {
+        return false;
+    }
+    digits.len() < max.len() || (digits.len() == max.len() && digits <= max)
+}
+
+/// Checks `words` against the answer schema of a job spec
+pub fn check_words(schema: &[AnswerType], words: &[AnswerWord]) -> Result<(), AnswerError> {
+    if schema.len() != words.len() {
+        return Err(AnswerError::WrongLength { expected: schema.len(), found: words.len() });
+    }
+    for (index, (expected, word)) in schema.iter().zip(words).enumerate() {
+        if word.answer_type() != *expected {
+            return Err(AnswerError::WrongType { index, expected: *expected });
+        }
+        word.validate(index)?;
+    }
+    Ok(())
+}
+
+/// Encodes words as the `answer` passed to the consumer
+pub fn encode_words(words: &[AnswerWord]) -> Base64String {
+    base64::encode(serde_json::to_string(words).unwrap())
+}
+
+/// Decodes the `answer` of a request fulfilled with typed words
+pub fn decode_words(answer: &str) -> Result<Vec<AnswerWord>, AnswerError> {
+    let json = base64::decode(answer).map_err(|_| AnswerError::InvalidBase64)?;
+    let words: Vec<AnswerWord> = serde_json::from_slice(&json).map_err(|e| AnswerError::InvalidJson(e.to_string()))?;
+    for (index, word) in words.iter().enumerate() {
+        word.validate(index)?;
+    }
+    Ok(words)
+}
+
+/// Decodes the `answer` of a request fulfilled with one plain text value
+pub fn decode_text(answer: &str) -> Result<String, AnswerError> {
+    let bytes = base64::decode(answer).map_err(|_| AnswerError::InvalidBase64)?;
+    String::from_utf8(bytes).map_err(|_| AnswerError::InvalidUtf8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price_words() -> Vec<AnswerWord> {
+        vec![
+            AnswerWord::Int256("-1910".to_string()),
+            AnswerWord::Bytes("AAEC".to_string()),
+            AnswerWord::Bool(true),
+            AnswerWord::String("ETH".to_string()),
+        ]
+    }
+
+    #[test]
+    fn words_round_trip() {
+        let answer = encode_words(&price_words());
+        assert_eq!(
+            "[{\"type\":\"Int256\",\"value\":\"-1910\"},{\"type\":\"Bytes\",\"value\":\"AAEC\"},{\"type\":\"Bool\",\"value\":true},{\"type\":\"String\",\"value\":\"ETH\"}]",
+            decode_text(&answer).unwrap()
+        );
+        assert_eq!(Ok(price_words()), decode_words(&answer));
+    }
+
+    #[test]
+    fn check_words_against_schema() {
+        let schema = vec![AnswerType::Int256, AnswerType::Bytes, AnswerType::Bool, AnswerType::String];
+        assert_eq!(Ok(()), check_words(&schema, &price_words()));
+        assert_eq!(Err(AnswerError::WrongLength { expected: 3, found: 4 }), check_words(&schema[..3], &price_words()));
+        let mut words = price_words();
+        words.swap(2, 3);
+        assert_eq!(Err(AnswerError::WrongType { index: 2, expected: AnswerType::Bool }), check_words(&schema, &words));
+        words = price_words();
+        words[1] = AnswerWord::Bytes("not base64!".to_string());
+        assert_eq!(Err(AnswerError::InvalidBytes(1)), check_words(&schema, &words));
+    }
+
+    #[test]
+    fn int256_bounds() {
+        assert!(is_int256("0"));
+        assert!(is_int256(INT256_MAX));
+        assert!(is_int256(&format!("-{}", INT256_MIN_ABS)));
+        assert!(!is_int256(INT256_MIN_ABS));
+        assert!(!is_int256(&format!("{}0", INT256_MAX)));
+        assert!(!is_int256("-0"));
+        assert!(!is_int256("007"));
+        assert!(!is_int256("19.1"));
+        assert!(!is_int256(""));
+        assert!(!is_int256("-"));
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(Err(AnswerError::InvalidBase64), decode_text("not base64!"));
+        assert_eq!(Err(AnswerError::InvalidUtf8), decode_text(&base64::encode(&[0xff, 0xfe])));
+        assert!(matches!(decode_words(&base64::encode("19.1")), Err(AnswerError::InvalidJson(_))));
+        assert_eq!(Err(AnswerError::InvalidInt256(0)), decode_words(&base64::encode("[{\"type\":\"Int256\",\"value\":\"1e3\"}]")));
+        assert_eq!("Answer word 2 must be Bool", AnswerError::WrongType { index: 2, expected: AnswerType::Bool }.to_string());
+    }
+}
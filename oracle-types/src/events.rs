@@ -0,0 +1,90 @@
+//! NEP-297 event logs.
+//! Each event is logged as `EVENT_JSON:` followed by `{"standard", "version", "event", "data"}`.
+//! A contract lists its events in an enum tagged as `event` and `data`, and implements `EventKind`
+//! for it to name the standard they belong to.
+use near_sdk::env;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+const EVENT_JSON_PREFIX: &str = "EVENT_JSON:";
+
+pub trait EventKind: Serialize + DeserializeOwned {
+    const STANDARD: &'static str;
+    const VERSION: &'static str;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event<K> {
+    pub standard: String,
+    pub version: String,
+    #[serde(flatten)]
+    pub kind: K,
+}
+
+impl<K: EventKind> Event<K> {
+    pub fn new(kind: K) -> Self {
+        Self {
+            standard: K::STANDARD.to_string(),
+            version: K::VERSION.to_string(),
+            kind,
+        }
+    }
+
+    /// Formats the event as a NEP-297 log line
+    pub fn to_log_string(&self) -> String {
+        format!("{}{}", EVENT_JSON_PREFIX, serde_json::to_string(self).unwrap())
+    }
+
+    /// Parses a log line written by `emit`, returning `None` for other logs and other standards
+    pub fn from_log_string(log: &str) -> Option<Self> {
+        if !log.starts_with(EVENT_JSON_PREFIX) {
+            return None
+        }
+        let event: Self = serde_json::from_str(&log[EVENT_JSON_PREFIX.len()..]).ok()?;
+        if event.standard != K::STANDARD {
+            return None
+        }
+        Some(event)
+    }
+
+    pub fn emit(&self) {
+        env::log(self.to_log_string().as_bytes());
+    }
+}
+
+/// Shorthand for creating and logging an event
+pub fn emit<K: EventKind>(kind: K) {
+    Event::new(kind).emit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    #[serde(tag = "event", content = "data", rename_all = "snake_case")]
+    enum TestEventKind {
+        Ping(Vec<u64>),
+        Reset,
+    }
+
+    impl EventKind for TestEventKind {
+        const STANDARD: &'static str = "test";
+        const VERSION: &'static str = "1.0.0";
+    }
+
+    #[test]
+    fn log_round_trip() {
+        let event = Event::new(TestEventKind::Ping(vec![7]));
+        let log = event.to_log_string();
+        assert_eq!("EVENT_JSON:{\"standard\":\"test\",\"version\":\"1.0.0\",\"event\":\"ping\",\"data\":[7]}", log);
+        assert_eq!(Some(event), Event::from_log_string(&log));
+        assert_eq!(Some(Event::new(TestEventKind::Reset)), Event::from_log_string("EVENT_JSON:{\"standard\":\"test\",\"version\":\"1.0.0\",\"event\":\"reset\"}"));
+    }
+
+    #[test]
+    fn other_logs_are_ignored() {
+        assert_eq!(None, Event::<TestEventKind>::from_log_string("Ping"));
+        assert_eq!(None, Event::<TestEventKind>::from_log_string("EVENT_JSON:{\"standard\":\"other\",\"version\":\"1.0.0\",\"event\":\"reset\"}"));
+    }
+}
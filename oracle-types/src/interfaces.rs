@@ -0,0 +1,63 @@
+//! `ext_contract` interfaces of the oracle, the NEAR LINK token and oracle consumers.
+//! `ext_contract` keeps only the arguments of these methods, so each contract has a test that calls
+//! its methods and their interface with the same arguments, checking at compile time they agree.
+use near_sdk::ext_contract;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::AccountId;
+use serde::{Deserialize, Serialize};
+
+use crate::requests::{BatchRequest, OracleRequest, RequestId};
+use crate::Base64String;
+
+#[ext_contract(ext_oracle)]
+pub trait ExtOracle {
+    fn request(&mut self, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: Option<U64>) -> near_sdk::Promise;
+    fn store_request(&mut self, sender: AccountId, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: Option<U64>) -> Option<RequestId>;
+    fn request_batch(&mut self, nonce: U128, requests: Vec<BatchRequest>) -> near_sdk::Promise;
+    fn store_requests(&mut self, sender: AccountId, nonce: U128, requests: Vec<BatchRequest>) -> Vec<Option<RequestId>>;
+    fn fulfillment_post_callback(&mut self, account: AccountId, nonce: U128, node: AccountId);
+    fn post_cancel_request(&mut self, account: AccountId, nonce: U128, request: OracleRequest);
+    fn post_withdraw(&mut self, recipient: AccountId, amount: U128);
+    fn post_withdraw_earnings(&mut self, node: AccountId, amount: U128);
+}
+
+#[ext_contract(ext_link)]
+pub trait ExtLink {
+    fn get_allowance(&self, owner_id: AccountId, escrow_account_id: AccountId) -> U128;
+    fn inc_allowance(&mut self, escrow_account_id: AccountId, amount: U128);
+    fn transfer_from(&mut self, owner_id: AccountId, new_owner_id: AccountId, amount: U128);
+    fn transfer(&mut self, new_owner_id: AccountId, amount: U128);
+    fn ft_transfer_call(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>, msg: String) -> near_sdk::Promise;
+    fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128;
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance;
+}
+
+/// NEP-145: Storage balance of an account on the token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+/// NEP-141 receiver, which the oracle implements to take requests paid with `ft_transfer_call`
+#[ext_contract(ext_ft_receiver)]
+pub trait ExtFtReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128;
+}
+
+/// Callback a consumer exposes for its answers. The method name is the request's
+/// `callback_method`, `token_price_callback` for the example client.
+/// `request_id` is optional for consumers, as oracles without request IDs don't send it.
+#[ext_contract(ext_consumer)]
+pub trait ExtConsumer {
+    fn token_price_callback(&mut self, nonce: U128, answer: Base64String, request_id: Option<RequestId>);
+}
+
+/// Arguments of a consumer callback, for calling a `callback_method` only known at runtime.
+/// The fields match the arguments of `ExtConsumer::token_price_callback`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConsumerCallbackArgs {
+    pub nonce: U128,
+    pub answer: Base64String,
+    pub request_id: Option<RequestId>,
+}
@@ -0,0 +1,10 @@
+//! Types shared by the oracle, the NEAR LINK token and consumer contracts.
+//! Contracts call each other through the interfaces here, so a signature that drifts on one side
+//! no longer compiles on the other.
+pub mod answers;
+pub mod events;
+pub mod interfaces;
+pub mod request_params;
+pub mod requests;
+
+pub type Base64String = String;
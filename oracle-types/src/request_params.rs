@@ -0,0 +1,128 @@
+//! Typed request parameters.
+//! A request's `data` is the Base64 encoded JSON of `RequestParams`, and its `data_version` says
+//! which version of the format is used. Only version 1 exists so far:
+//! `{"get": "https://...", "path": "USD", "times": 100}`
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const REQUEST_PARAMS_VERSION: u128 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RequestParams {
+    /// URL the node fetches
+    pub get: String,
+    /// Dot separated path to the answer in the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The answer is multiplied by this, so decimals can be returned as integers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestParamsError {
+    UnsupportedVersion(u128),
+    InvalidBase64,
+    InvalidJson(String),
+    InvalidUrl,
+    EmptyPath,
+    ZeroTimes,
+}
+
+impl fmt::Display for RequestParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestParamsError::UnsupportedVersion(version) => write!(f, "Unsupported request data version: {}", version),
+            RequestParamsError::InvalidBase64 => write!(f, "Request data is not valid Base64"),
+            RequestParamsError::InvalidJson(error) => write!(f, "Request data is not valid JSON: {}", error),
+            RequestParamsError::InvalidUrl => write!(f, "Request get must be an http or https URL"),
+            RequestParamsError::EmptyPath => write!(f, "Request path can't be empty"),
+            RequestParamsError::ZeroTimes => write!(f, "Request times must be greater than zero"),
+        }
+    }
+}
+
+impl RequestParams {
+    pub fn new(get: &str) -> Self {
+        Self { get: get.to_string(), path: None, times: None }
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn times(mut self, times: u64) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), RequestParamsError> {
+        if !(self.get.starts_with("https://") || self.get.starts_with("http://")) {
+            return Err(RequestParamsError::InvalidUrl);
+        }
+        if self.path.as_ref().map_or(false, |path| path.is_empty()) {
+            return Err(RequestParamsError::EmptyPath);
+        }
+        if self.times == Some(0) {
+            return Err(RequestParamsError::ZeroTimes);
+        }
+        Ok(())
+    }
+
+    /// Encodes the parameters as `data` for version `REQUEST_PARAMS_VERSION`
+    pub fn to_base64(&self) -> String {
+        base64::encode(serde_json::to_string(self).unwrap())
+    }
+
+    /// Decodes and validates the `data` of a request with the given `data_version`
+    pub fn from_base64(data_version: u128, data: &str) -> Result<Self, RequestParamsError> {
+        if data_version != REQUEST_PARAMS_VERSION {
+            return Err(RequestParamsError::UnsupportedVersion(data_version));
+        }
+        let json = base64::decode(data).map_err(|_| RequestParamsError::InvalidBase64)?;
+        let params: Self = serde_json::from_slice(&json).map_err(|e| RequestParamsError::InvalidJson(e.to_string()))?;
+        params.validate()?;
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETH_USD: &str = "eyJnZXQiOiJodHRwczovL21pbi1hcGkuY3J5cHRvY29tcGFyZS5jb20vZGF0YS9wcmljZT9mc3ltPUVUSCZ0c3ltcz1VU0QiLCJwYXRoIjoiVVNEIiwidGltZXMiOjEwMH0=";
+
+    #[test]
+    fn round_trip() {
+        let params = RequestParams::new("https://min-api.cryptocompare.com/data/price?fsym=ETH&tsyms=USD").path("USD").times(100);
+        assert_eq!(ETH_USD, params.to_base64());
+        assert_eq!(Ok(params), RequestParams::from_base64(1, ETH_USD));
+    }
+
+    #[test]
+    fn optional_keys() {
+        let params = RequestParams::new("https://example.com/price");
+        assert_eq!(Ok(params.clone()), RequestParams::from_base64(1, &params.to_base64()));
+    }
+
+    #[test]
+    fn malformed_data() {
+        assert_eq!(Err(RequestParamsError::UnsupportedVersion(2)), RequestParams::from_base64(2, ETH_USD));
+        assert_eq!(Err(RequestParamsError::InvalidBase64), RequestParams::from_base64(1, "not base64!"));
+        // "BAT"
+        assert!(matches!(RequestParams::from_base64(1, "QkFU"), Err(RequestParamsError::InvalidJson(_))));
+        let unknown_key = base64::encode("{\"get\":\"https://example.com\",\"copy\":\"USD\"}");
+        assert!(matches!(RequestParams::from_base64(1, &unknown_key), Err(RequestParamsError::InvalidJson(_))));
+        assert_eq!(Err(RequestParamsError::InvalidUrl), RequestParams::from_base64(1, &RequestParams::new("ftp://example.com").to_base64()));
+        assert_eq!(Err(RequestParamsError::EmptyPath), RequestParams::from_base64(1, &RequestParams::new("https://example.com").path("").to_base64()));
+        assert_eq!(Err(RequestParamsError::ZeroTimes), RequestParams::from_base64(1, &RequestParams::new("https://example.com").times(0).to_base64()));
+    }
+
+    #[test]
+    fn error_messages() {
+        assert_eq!("Unsupported request data version: 2", RequestParamsError::UnsupportedVersion(2).to_string());
+        assert_eq!("Request get must be an http or https URL", RequestParamsError::InvalidUrl.to_string());
+    }
+}
@@ -0,0 +1,57 @@
+//! Requests as the oracle stores them and returns them from its views.
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, AccountId};
+use serde::{Deserialize, Serialize};
+
+use crate::Base64String;
+
+/// Base64 encoded sha256 hash identifying a request across oracles
+pub type RequestId = Base64String;
+
+/// Deterministic ID of the request `nonce` of `caller` on `oracle`: the sha256 hash of the Borsh
+/// serialization of `(oracle, caller, nonce)`, so nodes and consumers can derive it themselves.
+pub fn request_id(oracle: &AccountId, caller: &AccountId, nonce: u128) -> RequestId {
+    let preimage = (oracle.clone(), caller.clone(), nonce).try_to_vec().unwrap();
+    base64::encode(env::sha256(&preimage))
+}
+
+#[derive(Default, BorshDeserialize, BorshSerialize, Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct OracleRequest {
+    pub caller_account: AccountId,
+    pub request_spec: Base64String,
+    pub callback_address: AccountId,
+    pub callback_method: String,
+    pub data_version: u128,
+    pub data: Base64String,
+    pub payment: u128,
+    pub expiration: u64,
+    /// Gas the consumer callback gets
+    pub callback_gas: u64,
+}
+
+/// One request of a `request_batch`, with the arguments of `request` except the nonce
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BatchRequest {
+    pub payment: U128,
+    pub spec_id: Base64String,
+    pub callback_address: AccountId,
+    pub callback_method: String,
+    pub data_version: U128,
+    pub data: Base64String,
+    pub callback_gas: Option<U64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SummaryJSON {
+    pub account: AccountId,
+    pub total_requests: u16, // TODO: choosing u16? need to enforce if so
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RequestsJSON {
+    pub nonce: U128,
+    pub request_id: RequestId,
+    pub request: OracleRequest,
+}
@@ -0,0 +1,209 @@
+//! Events of the oracle, logged with the NEP-297 envelope of `oracle_types::events`,
+//! so off-chain nodes and indexers can parse state transitions instead of scraping messages.
+use near_sdk::json_types::{U128, U64};
+use near_sdk::AccountId;
+use oracle_types::events::{self, Event, EventKind};
+use serde::{Deserialize, Serialize};
+
+use crate::{Base64String, PauseTarget, RequestId, Role};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OracleRequestData {
+    pub account: AccountId,
+    pub nonce: U128,
+    pub request_id: RequestId,
+    pub spec_id: Base64String,
+    pub callback_address: AccountId,
+    pub callback_method: String,
+    pub data_version: U128,
+    pub data: Base64String,
+    pub payment: U128,
+    pub expiration: U64,
+    pub callback_gas: U64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestFulfilledData {
+    pub account: AccountId,
+    pub nonce: U128,
+    pub payment: U128,
+    pub node: AccountId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CallbackFailedData {
+    pub account: AccountId,
+    pub nonce: U128,
+    pub payment: U128,
+    pub node: AccountId,
+    pub reason: String,
+}
+
+/// A request in a `fulfill_requests` batch that was left pending
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FulfillmentSkippedData {
+    pub account: AccountId,
+    pub nonce: U128,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestCancelledData {
+    pub account: AccountId,
+    pub nonce: U128,
+    pub refund: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NodeData {
+    pub node: AccountId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WithdrawalData {
+    pub recipient: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoleData {
+    pub role: Role,
+    pub account: AccountId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OwnershipData {
+    pub previous_owner: AccountId,
+    pub new_owner: AccountId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PauseData {
+    pub target: PauseTarget,
+    pub paused: bool,
+}
+
+/// The event name and its data, tagged as `event` and `data` in the log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum OracleEventKind {
+    OracleRequest(Vec<OracleRequestData>),
+    RequestFulfilled(Vec<RequestFulfilledData>),
+    CallbackFailed(Vec<CallbackFailedData>),
+    FulfillmentSkipped(Vec<FulfillmentSkippedData>),
+    RequestCancelled(Vec<RequestCancelledData>),
+    NodeAuthorized(Vec<NodeData>),
+    NodeRemoved(Vec<NodeData>),
+    Withdrawal(Vec<WithdrawalData>),
+    RoleGranted(Vec<RoleData>),
+    RoleRevoked(Vec<RoleData>),
+    OwnershipTransferred(Vec<OwnershipData>),
+    PauseChanged(Vec<PauseData>),
+    Reset,
+}
+
+impl EventKind for OracleEventKind {
+    const STANDARD: &'static str = "oracle";
+    const VERSION: &'static str = "1.0.0";
+}
+
+pub type OracleEvent = Event<OracleEventKind>;
+
+/// Shorthand for creating and logging an event
+pub fn emit(kind: OracleEventKind) {
+    events::emit(kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(kind: OracleEventKind) -> String {
+        let event = OracleEvent::new(kind);
+        let log = event.to_log_string();
+        assert_eq!(Some(event), OracleEvent::from_log_string(&log));
+        log
+    }
+
+    #[test]
+    fn oracle_request_round_trip() {
+        let log = round_trip(OracleEventKind::OracleRequest(vec![OracleRequestData {
+            account: "client.testnet".to_string(),
+            nonce: U128(1),
+            request_id: "Bx7Y2Tb7Do4AAFHhVl6nADPnUdH+0bNo0hrdwn3vu9o=".to_string(),
+            spec_id: "dW5pcXVlIHNwZWMgaWQ=".to_string(),
+            callback_address: "client.testnet".to_string(),
+            callback_method: "token_price_callback".to_string(),
+            data_version: U128(1),
+            data: "QkFU".to_string(),
+            payment: U128(10),
+            expiration: U64(300_000_000_000),
+            callback_gas: U64(50_000_000_000_000),
+        }]));
+        assert_eq!("EVENT_JSON:{\"standard\":\"oracle\",\"version\":\"1.0.0\",\"event\":\"oracle_request\",\"data\":[{\"account\":\"client.testnet\",\"nonce\":\"1\",\"request_id\":\"Bx7Y2Tb7Do4AAFHhVl6nADPnUdH+0bNo0hrdwn3vu9o=\",\"spec_id\":\"dW5pcXVlIHNwZWMgaWQ=\",\"callback_address\":\"client.testnet\",\"callback_method\":\"token_price_callback\",\"data_version\":\"1\",\"data\":\"QkFU\",\"payment\":\"10\",\"expiration\":\"300000000000\",\"callback_gas\":\"50000000000000\"}]}", log);
+    }
+
+    #[test]
+    fn fulfillment_events_round_trip() {
+        round_trip(OracleEventKind::RequestFulfilled(vec![RequestFulfilledData {
+            account: "client.testnet".to_string(),
+            nonce: U128(1),
+            payment: U128(10),
+            node: "oracle-node.testnet".to_string(),
+        }]));
+        round_trip(OracleEventKind::CallbackFailed(vec![CallbackFailedData {
+            account: "client.testnet".to_string(),
+            nonce: U128(2),
+            payment: U128(10),
+            node: "oracle-node.testnet".to_string(),
+            reason: "Callback token_price_callback on client.testnet failed.".to_string(),
+        }]));
+        round_trip(OracleEventKind::FulfillmentSkipped(vec![FulfillmentSkippedData {
+            account: "client.testnet".to_string(),
+            nonce: U128(3),
+            reason: "Request has expired and can no longer be fulfilled.".to_string(),
+        }]));
+        round_trip(OracleEventKind::RequestCancelled(vec![RequestCancelledData {
+            account: "client.testnet".to_string(),
+            nonce: U128(3),
+            refund: U128(10),
+        }]));
+    }
+
+    #[test]
+    fn admin_events_round_trip() {
+        let log = round_trip(OracleEventKind::NodeAuthorized(vec![NodeData { node: "oracle-node.testnet".to_string() }]));
+        assert_eq!("EVENT_JSON:{\"standard\":\"oracle\",\"version\":\"1.0.0\",\"event\":\"node_authorized\",\"data\":[{\"node\":\"oracle-node.testnet\"}]}", log);
+        round_trip(OracleEventKind::NodeRemoved(vec![NodeData { node: "oracle-node.testnet".to_string() }]));
+        round_trip(OracleEventKind::Withdrawal(vec![WithdrawalData {
+            recipient: "oracle-node.testnet".to_string(),
+            amount: U128(10),
+        }]));
+        let log = round_trip(OracleEventKind::RoleGranted(vec![RoleData {
+            role: Role::NodeManager,
+            account: "ops.testnet".to_string(),
+        }]));
+        assert_eq!("EVENT_JSON:{\"standard\":\"oracle\",\"version\":\"1.0.0\",\"event\":\"role_granted\",\"data\":[{\"role\":\"NodeManager\",\"account\":\"ops.testnet\"}]}", log);
+        round_trip(OracleEventKind::RoleRevoked(vec![RoleData {
+            role: Role::Treasurer,
+            account: "ops.testnet".to_string(),
+        }]));
+        let log = round_trip(OracleEventKind::OwnershipTransferred(vec![OwnershipData {
+            previous_owner: "oracle.testnet".to_string(),
+            new_owner: "dao.testnet".to_string(),
+        }]));
+        assert_eq!("EVENT_JSON:{\"standard\":\"oracle\",\"version\":\"1.0.0\",\"event\":\"ownership_transferred\",\"data\":[{\"previous_owner\":\"oracle.testnet\",\"new_owner\":\"dao.testnet\"}]}", log);
+        let log = round_trip(OracleEventKind::PauseChanged(vec![PauseData {
+            target: PauseTarget::Fulfillment,
+            paused: true,
+        }]));
+        assert_eq!("EVENT_JSON:{\"standard\":\"oracle\",\"version\":\"1.0.0\",\"event\":\"pause_changed\",\"data\":[{\"target\":\"Fulfillment\",\"paused\":true}]}", log);
+        let log = round_trip(OracleEventKind::Reset);
+        assert_eq!("EVENT_JSON:{\"standard\":\"oracle\",\"version\":\"1.0.0\",\"event\":\"reset\"}", log);
+    }
+
+    #[test]
+    fn other_logs_are_not_events() {
+        assert_eq!(None, OracleEvent::from_log_string("Request has been cancelled and refunded."));
+    }
+}
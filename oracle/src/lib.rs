@@ -2,57 +2,146 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Serialize, Deserialize};
 use near_sdk::collections::{TreeMap, UnorderedSet};
 use near_sdk::json_types::{U128, U64};
-use near_sdk::{AccountId, env, near_bindgen, PromiseResult};
-use serde_json::json;
+use near_sdk::{AccountId, env, near_bindgen, Promise, PromiseResult};
 use std::str;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use oracle_types::answers::{check_words, encode_words, AnswerType, AnswerWord};
+use oracle_types::interfaces::{ext_link, ext_oracle, ConsumerCallbackArgs};
+use oracle_types::request_params::{RequestParams, REQUEST_PARAMS_VERSION};
+pub use oracle_types::Base64String;
+pub use oracle_types::requests::{request_id, BatchRequest, OracleRequest, RequestId, RequestsJSON, SummaryJSON};
+
+pub mod events;
+use events::{OracleEventKind, OracleRequestData, RequestFulfilledData, CallbackFailedData, FulfillmentSkippedData, RequestCancelledData, NodeData, WithdrawalData, RoleData, OwnershipData, PauseData};
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// Default expiry window, used when a request's job spec is no longer registered.
 const EXPIRY_TIME: u64 = 5 * 60 * 1_000_000_000;
 
 // max gas: 300_000_000_000_000
 
-const MINIMUM_CONSUMER_GAS_LIMIT: u64 = 1_000_000_000;
 const SINGLE_CALL_GAS: u64 = 50_000_000_000_000; // 5 x 10^13
-const TRANSFER_FROM_NEAR_COST: u128 = 36_500_000_000_000_000_000_000; // 365 x 10^20
 
-pub type Base64String = String;
+/// Gas for the consumer callback of requests that don't set `callback_gas`
+const DEFAULT_CALLBACK_GAS: u64 = SINGLE_CALL_GAS;
+const DEFAULT_MIN_CALLBACK_GAS: u64 = 5_000_000_000_000;
+const DEFAULT_MAX_CALLBACK_GAS: u64 = 200_000_000_000_000;
+/// Gas for `fulfillment_post_callback`, which the node has to attach on top of the callback gas
+const FULFILLMENT_POST_CALLBACK_GAS: u64 = SINGLE_CALL_GAS;
+/// Most requests in one `request_batch`, so `store_requests` fits in a transaction's gas
+const MAX_BATCH_REQUESTS: usize = 10;
+/// Gas for `store_request` and `store_requests` is the base, which covers refunding requests that can't be stored, plus this for each request
+const STORE_REQUESTS_BASE_GAS: u64 = SINGLE_CALL_GAS + 10_000_000_000_000;
+const STORE_REQUEST_GAS: u64 = 10_000_000_000_000;
+
+/// Settings of a job the oracle node runs, keyed by its Base64 `spec_id`
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct JobSpec {
+    min_payment: u128,
+    expiry_time: u64,
+    enabled: bool,
+    /// Types of the answer words, the job is fulfilled with `fulfill_request_words` if set
+    answer_schema: Option<Vec<AnswerType>>,
+}
+
+/// A request whose consumer callback failed during fulfillment
+#[derive(BorshDeserialize, BorshSerialize, Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct FailedFulfillment {
+    request: OracleRequest,
+    reason: String,
+    failed_at: u64,
+}
+
+/// Administrative roles the owner can grant, each limited to part of the owner's powers
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    /// Can add and remove authorized nodes
+    NodeManager,
+    /// Can withdraw earned LINK
+    Treasurer,
+    /// Can pause and unpause the oracle
+    Pauser,
+}
+
+/// Entry points that can be paused independently of each other
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PauseTarget {
+    /// New requests through `request`
+    Intake,
+    /// `fulfill_request` by authorized nodes
+    Fulfillment,
+    /// `withdraw` of earned LINK
+    Withdrawals,
+}
 
 #[derive(Default, BorshDeserialize, BorshSerialize, Debug, Clone)]
 #[derive(Serialize, Deserialize)]
-pub struct OracleRequest {
-    caller_account: AccountId,
-    request_spec: Base64String,
+pub struct PauseState {
+    intake: bool,
+    fulfillment: bool,
+    withdrawals: bool,
+}
+
+/// Request arguments passed as `msg` of `ft_transfer_call` on the LINK token
+#[derive(Serialize, Deserialize)]
+pub struct TransferRequestMsg {
+    payment: Option<U128>,
+    spec_id: Base64String,
     callback_address: AccountId,
     callback_method: String,
+    nonce: U128,
+    /// Version of the `data` format, defaults to 1
+    data_version: Option<U128>,
     data: Base64String,
-    payment: u128,
-    expiration: u64
+    callback_gas: Option<U64>,
 }
 
+/// One request of a `fulfill_requests` batch
 #[derive(Serialize, Deserialize)]
-pub struct SummaryJSON {
-    account: AccountId,
-    total_requests: u16, // TODO: choosing u16? need to enforce if so
+pub struct FulfillmentArgs {
+    pub account: AccountId,
+    pub nonce: U128,
+    pub data: Base64String,
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct RequestsJSON {
+pub struct FailedFulfillmentJSON {
     nonce: U128,
-    request: OracleRequest,
+    failure: FailedFulfillment,
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Oracle {
     pub owner: AccountId,
+    pub pending_owner: Option<AccountId>,
     pub link_account: AccountId,
+    /// LINK the owner can withdraw: the owner's fee and payments of requests the oracle fulfilled itself
     pub withdrawable_tokens: u128,
+    /// LINK each node earned by fulfilling requests, which the node can withdraw
+    pub node_earnings: TreeMap<AccountId, u128>,
+    /// Percentage of each payment that goes to the owner instead of the fulfilling node
+    pub owner_fee_percentage: u8,
     pub nonces: TreeMap<AccountId, U128>,
     pub requests: TreeMap<AccountId, TreeMap<u128, OracleRequest>>,
+    /// Request ID => (account, nonce) of each stored request
+    pub request_ids: TreeMap<RequestId, (AccountId, u128)>,
+    /// IDs of requests whose consumer callback was sent and whose `fulfillment_post_callback` hasn't run yet
+    pub fulfilling: UnorderedSet<RequestId>,
     pub authorized_nodes: UnorderedSet<AccountId>,
+    pub job_specs: TreeMap<Base64String, JobSpec>,
+    pub failed_fulfillments: TreeMap<AccountId, TreeMap<u128, FailedFulfillment>>,
+    pub node_managers: UnorderedSet<AccountId>,
+    pub treasurers: UnorderedSet<AccountId>,
+    pub pausers: UnorderedSet<AccountId>,
+    pub pause_state: PauseState,
+    /// Bounds of the `callback_gas` a request can set
+    pub min_callback_gas: u64,
+    pub max_callback_gas: u64,
 }
 
 impl Default for Oracle {
@@ -71,85 +160,111 @@ impl Oracle {
         assert!(!env::state_exists(), "Already initialized");
         Self {
             owner: owner_id,
+            pending_owner: None,
             link_account: link_id,
             withdrawable_tokens: 0_u128,
+            node_earnings: TreeMap::new(b"node_earnings".to_vec()),
+            owner_fee_percentage: 0,
             nonces: TreeMap::new(b"nonces".to_vec()),
             requests: TreeMap::new(b"requests".to_vec()),
+            request_ids: TreeMap::new(b"request_ids".to_vec()),
+            fulfilling: UnorderedSet::new(b"fulfilling".to_vec()),
             authorized_nodes: UnorderedSet::new(b"authorized_nodes".to_vec()),
+            job_specs: TreeMap::new(b"job_specs".to_vec()),
+            failed_fulfillments: TreeMap::new(b"failed_fulfillments".to_vec()),
+            node_managers: UnorderedSet::new(b"node_managers".to_vec()),
+            treasurers: UnorderedSet::new(b"treasurers".to_vec()),
+            pausers: UnorderedSet::new(b"pausers".to_vec()),
+            pause_state: PauseState::default(),
+            min_callback_gas: DEFAULT_MIN_CALLBACK_GAS,
+            max_callback_gas: DEFAULT_MAX_CALLBACK_GAS,
         }
     }
 
     /// This is the entry point that will use the escrow transfer_from.
-    /// Afterwards, it essentially calls itself (store_request) which stores the request in state.
-    pub fn request(&mut self, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String) {
-        self._check_callback_address(&callback_address);
-        let nonce_u128: u128 = nonce.into();
+    /// Afterwards, it essentially calls itself (store_request) which stores the request in state
+    /// and returns its request ID.
+    /// `callback_gas` is the gas the consumer callback gets, within the owner's bounds.
+    pub fn request(&mut self, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: Option<U64>) -> Promise {
+        self._assert_not_paused(PauseTarget::Intake);
+        self._check_request(&env::predecessor_account_id(), payment.into(), &spec_id, &callback_address, nonce.into());
+        // reject malformed data and callback gas before the tokens are transferred
+        self._check_request_params(data_version.into(), &data);
+        let callback_gas = Some(U64(self._check_callback_gas(callback_gas)));
+
+        // first transfer token, the oracle is registered with the token so no deposit is needed
+        let promise_transfer_tokens = ext_link::transfer_from(env::predecessor_account_id(), env::current_account_id(), payment, &self.link_account, 0, SINGLE_CALL_GAS);
 
-        let entry_option = self.requests.get(&env::predecessor_account_id());
-        if entry_option.is_some() {
-            // Ensure there isn't already the same nonce
-            let nonce_entry = entry_option.unwrap();
-            if nonce_entry.contains_key(&nonce_u128) {
-                env::panic(b"Existing account and nonce in requests");
-            }
+        // call this contract's request function after the transfer
+        promise_transfer_tokens.then(ext_oracle::store_request(env::predecessor_account_id(), payment, spec_id, callback_address, callback_method, nonce, data_version, data, callback_gas, &env::current_account_id(), 0, STORE_REQUESTS_BASE_GAS + STORE_REQUEST_GAS))
+    }
+
+    /// Accounts/contracts should call request, which in turn calls this contract via a promise.
+    /// The data and callback gas were checked by `request`, so they're stored as they are.
+    /// Another request from `sender` may have taken the nonce in the meantime, then the request isn't stored,
+    /// its payment is refunded and `None` is returned.
+    pub fn store_request(&mut self, sender: AccountId, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: Option<U64>) -> Option<RequestId> {
+        // this method should only ever be called from this contract
+        self._only_owner_predecessor();
+
+        // TODO: fix this "if" workaround until I can figure out how to write tests with promises
+        if cfg!(target_arch = "wasm32") {
+            assert_eq!(env::promise_results_count(), 1);
+            // ensure successful promise, meaning tokens are transferred
+            match env::promise_result(0) {
+                PromiseResult::Successful(_) => {},
+                PromiseResult::Failed => env::panic(b"The promise failed. See receipt failures."),
+                PromiseResult::NotReady => env::panic(b"The promise was not ready."),
+            };
         }
 
-        let last_nonce_option: Option<U128> = self.get_nonce(env::predecessor_account_id());
-        if last_nonce_option.is_some() {
-            let last_nonce_u128: u128 = last_nonce_option.unwrap().into();
-            assert!(last_nonce_u128 < nonce_u128, format!("Invalid, already used nonce: {:?}", nonce_u128));
+        if !self._is_nonce_available(&sender, nonce.into()) {
+            env::log(format!("Nonce {} of {} can't be stored, the payment is refunded.", nonce.0, sender).as_bytes());
+            ext_link::transfer(sender, payment, &self.link_account, 0, SINGLE_CALL_GAS);
+            return None
         }
-        let has_nonce_option = self.nonces.get(&env::predecessor_account_id());
-        let transfer_cost = if has_nonce_option.is_some() {
-            0u128
-        } else {
-            TRANSFER_FROM_NEAR_COST
-        };
-        // first transfer token
-        let promise_transfer_tokens = env::promise_create(
-            self.link_account.clone(),
-            b"transfer_from",
-            json!({
-                "owner_id": env::predecessor_account_id(),
-                "new_owner_id": env::current_account_id(),
-                "amount": payment,
-            }).to_string().as_bytes(),
-            transfer_cost,
-            SINGLE_CALL_GAS,
-        );
+        let callback_gas = self._callback_gas(callback_gas);
+        Some(self._store_request(sender, payment, spec_id, callback_address, callback_method, nonce, data_version, data, callback_gas))
+    }
 
-        // call this contract's request function after the transfer
-        let promise_call_self_request = env::promise_then(
-            promise_transfer_tokens,
-            env::current_account_id(),
-            b"store_request",
-            json!({
-                "sender": env::predecessor_account_id(),
-                "payment": payment,
-                "spec_id": spec_id,
-                "callback_address": callback_address,
-                "callback_method": callback_method,
-                "nonce": nonce,
-                "data_version": data_version,
-                "data": data
-            }).to_string().as_bytes(),
-            0,
-            SINGLE_CALL_GAS
-        );
+    /// Makes several requests with consecutive nonces, starting at `nonce`, paying for all of them with one `transfer_from`.
+    /// The requests are stored together by `store_requests` once the transfer succeeded, so none are stored if it fails.
+    pub fn request_batch(&mut self, nonce: U128, requests: Vec<BatchRequest>) -> Promise {
+        self._assert_not_paused(PauseTarget::Intake);
+        assert!(!requests.is_empty(), "The batch has no requests.");
+        assert!(requests.len() <= MAX_BATCH_REQUESTS, "The batch can have at most {} requests.", MAX_BATCH_REQUESTS);
+        let sender = env::predecessor_account_id();
+        let first_nonce: u128 = nonce.into();
+        let mut total_payment: u128 = 0;
+        if first_nonce.checked_add(requests.len() as u128 - 1).is_none() {
+            env::panic(b"The batch nonces are out of range.");
+        }
+        let mut requests = requests;
+        for (offset, request) in requests.iter_mut().enumerate() {
+            let payment: u128 = request.payment.into();
+            self._check_request(&sender, payment, &request.spec_id, &request.callback_address, first_nonce + offset as u128);
+            self._check_request_params(request.data_version.into(), &request.data);
+            request.callback_gas = Some(U64(self._check_callback_gas(request.callback_gas)));
+            total_payment = match total_payment.checked_add(payment) {
+                Some(total) => total,
+                None => env::panic(b"Total payment of the batch is too large."),
+            };
+        }
 
-        env::promise_return(promise_call_self_request);
+        let store_gas = STORE_REQUESTS_BASE_GAS + STORE_REQUEST_GAS * requests.len() as u64;
+        let promise_transfer_tokens = ext_link::transfer_from(sender.clone(), env::current_account_id(), U128(total_payment), &self.link_account, 0, SINGLE_CALL_GAS);
+        promise_transfer_tokens.then(ext_oracle::store_requests(sender, nonce, requests, &env::current_account_id(), 0, store_gas))
     }
 
-    /// Accounts/contracts should call request, which in turn calls this contract via a promise
-    #[allow(unused_variables)] // for data_version, which is also not used in Solidity as I understand
-    pub fn store_request(&mut self, sender: AccountId, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String) {
-        // this method should only ever be called from this contract
+    /// Stores the requests of `request_batch` after their payment is transferred, returning their request IDs.
+    /// Another request from `sender` may have taken a nonce in the meantime, those requests aren't stored
+    /// and their payment is refunded.
+    pub fn store_requests(&mut self, sender: AccountId, nonce: U128, requests: Vec<BatchRequest>) -> Vec<Option<RequestId>> {
         self._only_owner_predecessor();
 
         // TODO: fix this "if" workaround until I can figure out how to write tests with promises
         if cfg!(target_arch = "wasm32") {
             assert_eq!(env::promise_results_count(), 1);
-            // ensure successful promise, meaning tokens are transferred
             match env::promise_result(0) {
                 PromiseResult::Successful(_) => {},
                 PromiseResult::Failed => env::panic(b"The promise failed. See receipt failures."),
@@ -157,10 +272,64 @@ impl Oracle {
             };
         }
 
+        let first_nonce: u128 = nonce.into();
+        let mut refund: u128 = 0;
+        let mut request_ids: Vec<Option<RequestId>> = Vec::with_capacity(requests.len());
+        for (offset, request) in requests.into_iter().enumerate() {
+            let nonce = match first_nonce.checked_add(offset as u128) {
+                Some(nonce) if self._is_nonce_available(&sender, nonce) => nonce,
+                _ => {
+                    env::log(format!("Request {} of the batch can't be stored, its payment is refunded.", offset).as_bytes());
+                    refund += u128::from(request.payment);
+                    request_ids.push(None);
+                    continue
+                }
+            };
+            let callback_gas = self._callback_gas(request.callback_gas);
+            request_ids.push(Some(self._store_request(sender.clone(), request.payment, request.spec_id, request.callback_address, request.callback_method, U128(nonce), request.data_version, request.data, callback_gas)));
+        }
+        if refund > 0 {
+            ext_link::transfer(sender, U128(refund), &self.link_account, 0, SINGLE_CALL_GAS);
+        }
+        request_ids
+    }
+
+    /// NEP-141 receiver, called by the LINK token on `ft_transfer_call` so a request is paid for and created atomically.
+    /// `msg` holds the `request` arguments as JSON, where `payment` defaults to the transferred amount.
+    /// Returns the unused part of the transferred amount, which the LINK token refunds to the sender.
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> U128 {
+        assert_eq!(env::predecessor_account_id(), self.link_account, "Only LINK tokens can be transferred to the oracle.");
+        self._assert_not_paused(PauseTarget::Intake);
+        let request_msg: TransferRequestMsg = match serde_json::from_str(&msg) {
+            Ok(request_msg) => request_msg,
+            Err(error) => env::panic(format!("Invalid request message: {}", error).as_bytes()),
+        };
+        let amount_u128: u128 = amount.into();
+        let payment_u128: u128 = match request_msg.payment {
+            Some(payment) => payment.into(),
+            None => amount_u128,
+        };
+        assert!(payment_u128 <= amount_u128, "Payment is greater than the transferred amount.");
+        self._check_request(&sender_id, payment_u128, &request_msg.spec_id, &request_msg.callback_address, request_msg.nonce.into());
+        let data_version = request_msg.data_version.unwrap_or(U128(REQUEST_PARAMS_VERSION));
+        self._check_request_params(data_version.into(), &request_msg.data);
+        let callback_gas = self._check_callback_gas(request_msg.callback_gas);
+        self._store_request(sender_id, payment_u128.into(), request_msg.spec_id, request_msg.callback_address, request_msg.callback_method, request_msg.nonce, data_version, request_msg.data, callback_gas);
+        U128(amount_u128 - payment_u128)
+    }
+
+    /// Stores a request whose nonce the caller checked with `_check_request` or `_is_nonce_available`
+    fn _store_request(&mut self, sender: AccountId, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: u64) -> RequestId {
         // cast arguments in order to be formatted
         let payment_u128: u128 = payment.into();
         let nonce_u128: u128 = nonce.into();
-        let expiration: u64 = env::block_timestamp() + EXPIRY_TIME;
+        assert!(self._is_nonce_available(&sender, nonce_u128), "Nonce {} of {} is already used.", nonce_u128, sender);
+        let data_version_u128: u128 = data_version.into();
+        let expiry_time = match self.job_specs.get(&spec_id) {
+            Some(job_spec) => job_spec.expiry_time,
+            None => EXPIRY_TIME,
+        };
+        let expiration: u64 = env::block_timestamp() + expiry_time;
 
         // store request
         let oracle_request = OracleRequest {
@@ -168,9 +337,11 @@ impl Oracle {
             request_spec: spec_id,
             callback_address,
             callback_method,
+            data_version: data_version_u128,
             data,
             payment: payment_u128,
             expiration,
+            callback_gas,
         };
 
         // Insert request and commitment into state.
@@ -187,75 +358,239 @@ impl Oracle {
         nonce_request.insert(&nonce_u128, &oracle_request);
         self.requests.insert(&sender.clone(), &nonce_request);
         self.nonces.insert(&sender.clone(), &nonce.clone());
-        env::log(format!("Inserted request with\nKey: {:?}\nValue: {:?}", nonce_u128.clone(), oracle_request.clone()).as_bytes());
+        let request_id = self._request_id(&sender, nonce_u128);
+        self.request_ids.insert(&request_id, &(sender.clone(), nonce_u128));
+        events::emit(OracleEventKind::OracleRequest(vec![OracleRequestData {
+            account: sender,
+            nonce,
+            request_id: request_id.clone(),
+            spec_id: oracle_request.request_spec,
+            callback_address: oracle_request.callback_address,
+            callback_method: oracle_request.callback_method,
+            data_version,
+            data: oracle_request.data,
+            payment,
+            expiration: U64(expiration),
+            callback_gas: U64(callback_gas),
+        }]));
+        request_id
+    }
+
+    /// Requests can also be fulfilled by their request ID with `fulfill_request_by_id`
+    pub fn fulfill_request(&mut self, account: AccountId, nonce: U128, data: Base64String) -> Promise {
+        let request = self._request_to_fulfill(&account, nonce.into());
+        if self._answer_schema(&request.request_spec).is_some() {
+            env::panic(b"The job spec has an answer schema, fulfill with fulfill_request_words.");
+        }
+        self._fulfill(account, nonce, request, data)
+    }
+
+    /// Fulfills a request with typed answer words, checked against the answer schema of its job spec.
+    /// The consumer gets them as `answer`, which `oracle_types::answers::decode_words` decodes.
+    pub fn fulfill_request_words(&mut self, account: AccountId, nonce: U128, words: Vec<AnswerWord>) -> Promise {
+        let request = self._request_to_fulfill(&account, nonce.into());
+        let checked = match self._answer_schema(&request.request_spec) {
+            Some(schema) => check_words(&schema, &words),
+            None => words.iter().enumerate().try_for_each(|(index, word)| word.validate(index)),
+        };
+        if let Err(error) = checked {
+            env::panic(format!("Invalid answer: {}", error).as_bytes());
+        }
+        self._fulfill(account, nonce, request, encode_words(&words))
+    }
+
+    /// Fulfills several requests with plain answers in one transaction.
+    /// Each request gets its own consumer callback and `fulfillment_post_callback`, so a failing consumer
+    /// doesn't affect the others. Requests that can't be fulfilled are skipped and logged as `fulfillment_skipped`.
+    /// Returns for each request whether its callback was scheduled.
+    pub fn fulfill_requests(&mut self, fulfillments: Vec<FulfillmentArgs>) -> Vec<bool> {
+        self._assert_not_paused(PauseTarget::Fulfillment);
+        self._only_authorized_node();
+
+        let mut seen: HashSet<(AccountId, u128)> = HashSet::new();
+        let mut to_fulfill: Vec<(FulfillmentArgs, OracleRequest)> = Vec::new();
+        let mut scheduled: Vec<bool> = Vec::with_capacity(fulfillments.len());
+        let mut required_gas: u64 = 0;
+        for fulfillment in fulfillments {
+            let nonce: u128 = fulfillment.nonce.into();
+            let found = if seen.insert((fulfillment.account.clone(), nonce)) {
+                self._pending_request(&fulfillment.account, nonce)
+            } else {
+                Err("The request is already in this batch.")
+            };
+            let checked = found.and_then(|request| match self._answer_schema(&request.request_spec) {
+                Some(_) => Err("The job spec has an answer schema, fulfill with fulfill_request_words."),
+                None => Ok(request),
+            });
+            scheduled.push(checked.is_ok());
+            match checked {
+                Ok(request) => {
+                    required_gas += request.callback_gas + FULFILLMENT_POST_CALLBACK_GAS;
+                    to_fulfill.push((fulfillment, request));
+                }
+                Err(reason) => events::emit(OracleEventKind::FulfillmentSkipped(vec![FulfillmentSkippedData {
+                    account: fulfillment.account,
+                    nonce: fulfillment.nonce,
+                    reason: reason.to_string(),
+                }])),
+            }
+        }
+        assert!(env::prepaid_gas() - env::used_gas() > required_gas, "Must provide consumer enough gas, {} is required for the callbacks.", required_gas);
+
+        // NEAR can't return a joint promise, so each chain is scheduled on its own when it's dropped
+        for (fulfillment, request) in to_fulfill {
+            self._fulfill(fulfillment.account, fulfillment.nonce, request, fulfillment.data);
+        }
+        scheduled
     }
 
-    /// Note that the request_id here is String instead of Vec<u8> as might be expected from the Solidity contract
-    pub fn fulfill_request(&mut self, account: AccountId, nonce: U128, data: Base64String) {
+    fn _request_to_fulfill(&mut self, account: &AccountId, nonce: u128) -> OracleRequest {
+        self._assert_not_paused(PauseTarget::Fulfillment);
         self._only_authorized_node();
 
-        // TODO: this is probably going to be too low at first, adjust
-        assert!(env::prepaid_gas() - env::used_gas() > MINIMUM_CONSUMER_GAS_LIMIT, "Must provide consumer enough gas");
+        let request = match self._pending_request(account, nonce) {
+            Ok(request) => request,
+            Err(reason) => env::panic(reason.as_bytes()),
+        };
+        let required_gas = request.callback_gas + FULFILLMENT_POST_CALLBACK_GAS;
+        assert!(env::prepaid_gas() - env::used_gas() > required_gas, "Must provide consumer enough gas, {} is required for the callbacks.", required_gas);
+        request
+    }
+
+    /// Looks up a request that can still be fulfilled
+    fn _pending_request(&self, account: &AccountId, nonce: u128) -> Result<OracleRequest, &'static str> {
+        let account_requests = match self.requests.get(account) {
+            Some(account_requests) => account_requests,
+            None => return Err("Did not find the account to fulfill."),
+        };
+        let request = match account_requests.get(&nonce) {
+            Some(request) => request,
+            None => return Err("Did not find the request (nonce) to fulfill."),
+        };
+        if self.fulfilling.contains(&self._request_id(account, nonce)) {
+            return Err("Request is already being fulfilled.");
+        }
+        if self._is_expired(&request) {
+            return Err("Request has expired and can no longer be fulfilled.");
+        }
+        Ok(request)
+    }
+
+    fn _fulfill(&mut self, account: AccountId, nonce: U128, request: OracleRequest, data: Base64String) -> Promise {
+        let nonce_u128: u128 = nonce.into();
+        let request_id = self._request_id(&account, nonce_u128);
+        // the request can't be fulfilled again or cancelled until fulfillment_post_callback
+        self.fulfilling.insert(&request_id);
+        // the callback method is only known at runtime, so it can't go through ext_consumer
+        let callback_args = ConsumerCallbackArgs { nonce, answer: data, request_id: Some(request_id) };
+        let promise_perform_callback = Promise::new(request.callback_address).function_call(
+            request.callback_method.into_bytes(),
+            serde_json::to_vec(&callback_args).unwrap(),
+            0,
+            request.callback_gas
+        );
+
+        // the signer is the node that gets paid for the fulfillment
+        promise_perform_callback.then(ext_oracle::fulfillment_post_callback(account, nonce, env::signer_account_id(), &env::current_account_id(), 0, FULFILLMENT_POST_CALLBACK_GAS))
+    }
+
+    /// Fulfills the request with the ID returned by `request` and `store_request`
+    pub fn fulfill_request_by_id(&mut self, request_id: RequestId, data: Base64String) -> Promise {
+        let (account, nonce) = match self.request_ids.get(&request_id) {
+            Some(entry) => entry,
+            None => env::panic(b"Did not find the request ID to fulfill."),
+        };
+        self.fulfill_request(account, U128(nonce), data)
+    }
+
+    /// Removes the fulfilled request whether or not the consumer's callback succeeded.
+    /// The node delivered the answer either way, so it's paid for it.
+    pub fn fulfillment_post_callback(&mut self, account: AccountId, nonce: U128, node: AccountId) {
+        self._only_owner_predecessor();
+        assert_eq!(env::promise_results_count(), 1);
+        let callback_succeeded = match env::promise_result(0) {
+            PromiseResult::Successful(_) => true,
+            PromiseResult::Failed => false,
+            PromiseResult::NotReady => env::panic(b"The promise was not ready."),
+        };
+        self._complete_fulfillment(account, nonce.into(), callback_succeeded, node);
+    }
 
-        // Get the request
+    /// Cancels an expired request made by the caller and refunds its payment in LINK.
+    /// A request whose consumer callback was already sent can't be cancelled, as the consumer gets the answer.
+    /// The request is removed before the refund and restored in `post_cancel_request` if the transfer fails.
+    pub fn cancel_request(&mut self, nonce: U128) -> Promise {
+        let account = env::predecessor_account_id();
         let account_requests = self.requests.get(&account);
         if account_requests.is_none() {
-            env::panic(b"Did not find the account to fulfill.");
+            env::panic(b"Did not find the account to cancel.");
         }
+        let mut account_requests = account_requests.unwrap();
         let nonce_u128: u128 = nonce.into();
-        let request_option = account_requests.unwrap().get(&nonce_u128);
+        let request_option = account_requests.get(&nonce_u128);
         if request_option.is_none() {
-            env::panic(b"Did not find the request (nonce) to fulfill.");
+            env::panic(b"Did not find the request (nonce) to cancel.");
         }
         let request = request_option.unwrap();
+        assert!(self._is_expired(&request), "Request is not expired.");
+        assert!(!self.fulfilling.contains(&self._request_id(&account, nonce_u128)), "Request is being fulfilled and can't be cancelled.");
 
-        let promise_perform_callback = env::promise_create(
-            request.callback_address,
-            request.callback_method.as_bytes(),
-            json!({
-                "nonce": nonce.clone(),
-                "answer": data
-            }).to_string().as_bytes(),
-            0,
-            SINGLE_CALL_GAS
-        );
+        // Remove request from state before refunding
+        account_requests.remove(&nonce_u128);
+        self.requests.insert(&account, &account_requests);
+        self.request_ids.remove(&self._request_id(&account, nonce_u128));
 
-        let promise_post_callback = env::promise_then(
-            promise_perform_callback,
-            env::current_account_id(),
-            b"fulfillment_post_callback",
-            json!({
-                "account": account,
-                "nonce": nonce
-            }).to_string().as_bytes(),
-            0,
-            SINGLE_CALL_GAS
-        );
+        let promise_refund = ext_link::transfer(account.clone(), U128(request.payment), &self.link_account, 0, SINGLE_CALL_GAS);
 
-        env::promise_return(promise_post_callback);
+        promise_refund.then(ext_oracle::post_cancel_request(account, nonce, request, &env::current_account_id(), 0, SINGLE_CALL_GAS))
     }
 
-    pub fn fulfillment_post_callback(&mut self, account: AccountId, nonce: U128) {
+    /// Restores a cancelled request if the LINK refund did not go through.
+    pub fn post_cancel_request(&mut self, account: AccountId, nonce: U128, request: OracleRequest) {
         self._only_owner_predecessor();
         // TODO: fix this "if" workaround until I can figure out how to write tests with promises
         if cfg!(target_arch = "wasm32") {
             assert_eq!(env::promise_results_count(), 1);
-            // ensure successful promise, meaning tokens are transferred
             match env::promise_result(0) {
                 PromiseResult::Successful(_) => {},
-                PromiseResult::Failed => env::panic(b"(fulfillment_post_callback) The promise failed. See receipt failures."),
+                PromiseResult::Failed => {
+                    let mut account_requests = self.requests.get(&account).unwrap_or_else(|| TreeMap::new(account.clone().into_bytes()));
+                    let nonce_u128: u128 = nonce.into();
+                    account_requests.insert(&nonce_u128, &request);
+                    self.requests.insert(&account, &account_requests);
+                    self.request_ids.insert(&self._request_id(&account, nonce_u128), &(account.clone(), nonce_u128));
+                    env::log(b"Refund failed, request has been restored.");
+                    return
+                },
                 PromiseResult::NotReady => env::panic(b"The promise was not ready."),
             };
         }
-        // Remove request from state
-        let mut account_requests = self.requests.get(&account).unwrap();
-        let nonce_u128: u128 = nonce.into();
-        let payment = account_requests.get(&nonce_u128).unwrap().payment;
-        account_requests.remove(&nonce_u128);
-        // Must overwrite the new TreeMap with the account key
-        self.requests.insert(&account, &account_requests);
-        env::log(b"Request has completed successfully and been removed.");
-        self.withdrawable_tokens += payment;
+        events::emit(OracleEventKind::RequestCancelled(vec![RequestCancelledData {
+            account,
+            nonce,
+            refund: U128(request.payment),
+        }]));
+    }
+
+    /// Returns up to `max_requests` requests of `account` whose consumer callback failed
+    pub fn get_failed_fulfillments(&self, account: AccountId, max_requests: U64) -> Vec<FailedFulfillmentJSON> {
+        let max_requests_u64: u64 = max_requests.into();
+        let mut result: Vec<FailedFulfillmentJSON> = Vec::new();
+        let account_failures_option = self.failed_fulfillments.get(&account);
+        if account_failures_option.is_none() {
+            return result
+        }
+
+        for failure in account_failures_option.unwrap().iter() {
+            if result.len() as u64 == max_requests_u64 {
+                break
+            }
+            result.push(FailedFulfillmentJSON {
+                nonce: U128(failure.0),
+                failure: failure.1,
+            });
+        }
+        result
     }
 
     pub fn is_authorized(&self, node: AccountId) -> bool {
@@ -263,65 +598,176 @@ impl Oracle {
     }
 
     pub fn add_authorization(&mut self, node: AccountId) {
-        self._only_owner();
+        self._only_owner_or_role(Role::NodeManager);
         assert!(env::is_valid_account_id(node.as_bytes()), "Account ID is invalid");
-        self.authorized_nodes.insert(&node);
+        if self.authorized_nodes.insert(&node) {
+            events::emit(OracleEventKind::NodeAuthorized(vec![NodeData { node }]));
+        }
     }
 
     pub fn remove_authorization(&mut self, node: AccountId) {
+        self._only_owner_or_role(Role::NodeManager);
+
+        if self.authorized_nodes.remove(&node) {
+            events::emit(OracleEventKind::NodeRemoved(vec![NodeData { node }]));
+        }
+    }
+
+    /// Registers or updates the job spec `spec_id` with its minimum payment and expiry window (in nanoseconds)
+    pub fn set_job_spec(&mut self, spec_id: Base64String, min_payment: U128, expiry_time: U64, enabled: bool) {
         self._only_owner();
+        let expiry_time_u64: u64 = expiry_time.into();
+        assert!(expiry_time_u64 > 0, "Expiry time must be greater than zero.");
+        let job_spec = JobSpec {
+            min_payment: min_payment.into(),
+            expiry_time: expiry_time_u64,
+            enabled,
+            answer_schema: self.job_specs.get(&spec_id).and_then(|job_spec| job_spec.answer_schema),
+        };
+        self.job_specs.insert(&spec_id, &job_spec);
+    }
 
-        self.authorized_nodes.remove(&node);
+    /// Declares the types of the answer words of the job spec `spec_id`, or removes them with `None`
+    pub fn set_answer_schema(&mut self, spec_id: Base64String, answer_schema: Option<Vec<AnswerType>>) {
+        self._only_owner();
+        let mut job_spec = match self.job_specs.get(&spec_id) {
+            Some(job_spec) => job_spec,
+            None => env::panic(format!("Unknown job spec: {}", spec_id).as_bytes()),
+        };
+        assert!(answer_schema.as_ref().map_or(true, |schema| !schema.is_empty()), "Answer schema can't be empty.");
+        job_spec.answer_schema = answer_schema;
+        self.job_specs.insert(&spec_id, &job_spec);
     }
 
-    pub fn withdraw(&mut self, recipient: AccountId, amount: U128) {
+    pub fn remove_job_spec(&mut self, spec_id: Base64String) {
         self._only_owner();
+        self.job_specs.remove(&spec_id);
+    }
+
+    pub fn get_job_spec(&self, spec_id: Base64String) -> Option<JobSpec> {
+        self.job_specs.get(&spec_id)
+    }
+
+    pub fn get_job_specs(&self) -> HashMap<Base64String, JobSpec> {
+        let mut result: HashMap<Base64String, JobSpec> = HashMap::new();
+        for job_spec in self.job_specs.iter() {
+            result.insert(job_spec.0, job_spec.1);
+        }
+        result
+    }
+
+    /// Withdraws LINK the owner earned.
+    /// The amount is deducted before the transfer and restored in `post_withdraw` if it fails.
+    pub fn withdraw(&mut self, recipient: AccountId, amount: U128) -> Promise {
+        self._assert_not_paused(PauseTarget::Withdrawals);
+        self._only_owner_or_role(Role::Treasurer);
         assert!(
             env::is_valid_account_id(recipient.as_bytes()),
             "Recipient account ID is invalid."
         );
         let amount_u128: u128 = amount.into();
         self._has_available_funds(amount_u128);
+        self.withdrawable_tokens -= amount_u128;
 
-        let promise_withdraw = env::promise_create(
-            self.link_account.clone(),
-            b"transfer",
-            json!({
-                "owner_id": env::current_account_id(),
-                "new_owner_id": recipient.clone(),
-                "amount": amount.clone(),
-            }).to_string().as_bytes(),
-            0,
-            SINGLE_CALL_GAS,
-        );
-
-        // call this contract's panic function after refunding
-        let promise_decrement_withdrawable = env::promise_then(
-            promise_withdraw,
-            env::current_account_id(),
-            b"post_withdraw",
-            json!({
-                "amount": amount.clone()
-            }).to_string().as_bytes(),
-            0,
-            SINGLE_CALL_GAS * 2
-        );
+        let promise_withdraw = ext_link::transfer(recipient.clone(), amount, &self.link_account, 0, SINGLE_CALL_GAS);
 
-        env::promise_return(promise_decrement_withdrawable);
+        promise_withdraw.then(ext_oracle::post_withdraw(recipient, amount, &env::current_account_id(), 0, SINGLE_CALL_GAS * 2))
     }
 
-    pub fn post_withdraw(&mut self, amount: U128) {
+    /// Restores the withdrawable tokens if the LINK transfer did not go through.
+    pub fn post_withdraw(&mut self, recipient: AccountId, amount: U128) {
         self._only_owner_predecessor();
-        assert_eq!(env::promise_results_count(), 1);
-        match env::promise_result(0) {
-            PromiseResult::Successful(_) => {},
-            PromiseResult::Failed => env::panic(b"(post_withdraw) The promise failed. See receipt failures."),
-            PromiseResult::NotReady => env::panic(b"The promise was not ready."),
-        };
+        // TODO: fix this "if" workaround until I can figure out how to write tests with promises
+        if cfg!(target_arch = "wasm32") {
+            assert_eq!(env::promise_results_count(), 1);
+            match env::promise_result(0) {
+                PromiseResult::Successful(_) => {},
+                PromiseResult::Failed => {
+                    self.withdrawable_tokens += amount.0;
+                    env::log(b"Withdrawal failed, withdrawable tokens have been restored.");
+                    return
+                },
+                PromiseResult::NotReady => env::panic(b"The promise was not ready."),
+            };
+        }
+        events::emit(OracleEventKind::Withdrawal(vec![WithdrawalData {
+            recipient,
+            amount,
+        }]));
+    }
 
+    /// Withdraws LINK the calling node earned by fulfilling requests.
+    /// The earnings are deducted before the transfer and restored in `post_withdraw_earnings` if it fails.
+    pub fn withdraw_earnings(&mut self, amount: U128) -> Promise {
+        self._assert_not_paused(PauseTarget::Withdrawals);
+        let node = env::predecessor_account_id();
         let amount_u128: u128 = amount.into();
-        self.withdrawable_tokens -= amount_u128.clone();
-        env::log(b"Decremented withdrawable tokens")
+        let earnings = self.node_earnings.get(&node).unwrap_or(0);
+        assert!(amount_u128 > 0, "Amount must be greater than zero.");
+        assert!(earnings >= amount_u128, "Amount requested is greater than the node's earnings.");
+        self._set_node_earnings(&node, earnings - amount_u128);
+
+        let promise_withdraw = ext_link::transfer(node.clone(), amount, &self.link_account, 0, SINGLE_CALL_GAS);
+
+        promise_withdraw.then(ext_oracle::post_withdraw_earnings(node, amount, &env::current_account_id(), 0, SINGLE_CALL_GAS))
+    }
+
+    /// Restores the node's earnings if the LINK transfer did not go through.
+    pub fn post_withdraw_earnings(&mut self, node: AccountId, amount: U128) {
+        self._only_owner_predecessor();
+        // TODO: fix this "if" workaround until I can figure out how to write tests with promises
+        if cfg!(target_arch = "wasm32") {
+            assert_eq!(env::promise_results_count(), 1);
+            match env::promise_result(0) {
+                PromiseResult::Successful(_) => {},
+                PromiseResult::Failed => {
+                    let earnings = self.node_earnings.get(&node).unwrap_or(0);
+                    self._set_node_earnings(&node, earnings + amount.0);
+                    env::log(b"Withdrawal failed, earnings have been restored.");
+                    return
+                },
+                PromiseResult::NotReady => env::panic(b"The promise was not ready."),
+            };
+        }
+        events::emit(OracleEventKind::Withdrawal(vec![WithdrawalData {
+            recipient: node,
+            amount,
+        }]));
+    }
+
+    pub fn get_node_earnings(&self, node: AccountId) -> U128 {
+        U128(self.node_earnings.get(&node).unwrap_or(0))
+    }
+
+    pub fn get_all_node_earnings(&self) -> HashMap<AccountId, U128> {
+        self.node_earnings.iter().map(|(node, earnings)| (node, U128(earnings))).collect()
+    }
+
+    /// Sets the percentage of each payment the owner keeps, the rest goes to the fulfilling node
+    pub fn set_owner_fee_percentage(&mut self, percentage: u8) {
+        self._only_owner();
+        assert!(percentage <= 100, "Owner fee percentage can't be more than 100.");
+        self.owner_fee_percentage = percentage;
+    }
+
+    pub fn get_owner_fee_percentage(&self) -> u8 {
+        self.owner_fee_percentage
+    }
+
+    /// Sets the bounds of the gas requests can reserve for their consumer callback
+    pub fn set_callback_gas_bounds(&mut self, min_callback_gas: U64, max_callback_gas: U64) {
+        self._only_owner();
+        let min_callback_gas: u64 = min_callback_gas.into();
+        let max_callback_gas: u64 = max_callback_gas.into();
+        assert!(min_callback_gas > 0, "Minimum callback gas must be greater than zero.");
+        assert!(min_callback_gas <= max_callback_gas, "Minimum callback gas can't be greater than the maximum.");
+        self.min_callback_gas = min_callback_gas;
+        self.max_callback_gas = max_callback_gas;
+    }
+
+    /// Returns the minimum and maximum callback gas
+    pub fn get_callback_gas_bounds(&self) -> (U64, U64) {
+        (U64(self.min_callback_gas), U64(self.max_callback_gas))
     }
 
     /// Get up to first 65K accounts that have their own associated nonces => requests
@@ -387,14 +833,32 @@ impl Oracle {
         }
         let nonce = req.0;
         let oracle_request = req.1;
-        result.push(RequestsJSON {
-            nonce: U128(nonce),
-            request: oracle_request,
-        });
+        result.push(self._requests_json(nonce, oracle_request));
 
         *counter += 1;
     }
 
+    /// Returns up to `max_requests` requests of `account` that have expired without being fulfilled.
+    /// These can be cancelled by the requester with `cancel_request`.
+    pub fn get_expired_requests(&self, account: AccountId, max_requests: U64) -> Vec<RequestsJSON> {
+        let max_requests_u64: u64 = max_requests.into();
+        let mut result: Vec<RequestsJSON> = Vec::new();
+        let account_requests_option = self.requests.get(&account);
+        if account_requests_option.is_none() {
+            return result
+        }
+
+        for req in account_requests_option.unwrap().iter() {
+            if result.len() as u64 == max_requests_u64 {
+                break
+            }
+            if self._is_expired(&req.1) {
+                result.push(self._requests_json(req.0, req.1));
+            }
+        }
+        result
+    }
+
     pub fn get_all_requests(&self, max_num_accounts: U64, max_requests: U64) -> HashMap<AccountId, Vec<RequestsJSON>> {
         let max_requests_u64: u64 = max_requests.into();
         let max_num_accounts_u64: u64 = max_num_accounts.into();
@@ -411,10 +875,7 @@ impl Oracle {
                 if request_counter == max_requests_u64 || request_counter > account_requests.1.len() {
                     break
                 }
-                let req = RequestsJSON {
-                    nonce: U128(nonce_request.0),
-                    request: nonce_request.1
-                };
+                let req = self._requests_json(nonce_request.0, nonce_request.1);
                 requests.push(req);
                 request_counter += 1;
             }
@@ -424,6 +885,17 @@ impl Oracle {
         result
     }
 
+    pub fn get_request_by_id(&self, request_id: RequestId) -> Option<RequestsJSON> {
+        let (account, nonce) = self.request_ids.get(&request_id)?;
+        let request = self.requests.get(&account)?.get(&nonce)?;
+        Some(self._requests_json(nonce, request))
+    }
+
+    /// Returns the request ID of the request `nonce` of `account`, whether or not it exists
+    pub fn get_request_id(&self, account: AccountId, nonce: U128) -> RequestId {
+        self._request_id(&account, nonce.into())
+    }
+
     pub fn get_nonce(&self, account: AccountId) -> Option<U128> {
         self.nonces.get(&account)
     }
@@ -440,74 +912,334 @@ impl Oracle {
         self.withdrawable_tokens
     }
 
-    pub fn reset(&mut self) {
-        self._only_owner();
-        self.requests.clear();
-        env::log(b"Commitments and requests are cleared.");
+    /// Pauses or unpauses one entry point, e.g. while a node or job spec is investigated
+    pub fn set_paused(&mut self, target: PauseTarget, paused: bool) {
+        self._only_owner_or_role(Role::Pauser);
+        let flag = match target {
+            PauseTarget::Intake => &mut self.pause_state.intake,
+            PauseTarget::Fulfillment => &mut self.pause_state.fulfillment,
+            PauseTarget::Withdrawals => &mut self.pause_state.withdrawals,
+        };
+        if *flag != paused {
+            *flag = paused;
+            events::emit(OracleEventKind::PauseChanged(vec![PauseData { target, paused }]));
+        }
     }
 
-    /// Can be called after a cross-contract call before enforcing a panic
-    pub fn panic(&mut self, error_message: String) {
-        self._only_owner_predecessor();
-        env::panic(error_message.as_bytes());
+    pub fn get_pause_state(&self) -> PauseState {
+        self.pause_state.clone()
     }
 
-    fn _has_available_funds(&mut self, amount: u128) {
-        assert!(self.withdrawable_tokens >= amount, "Amount requested is greater than withdrawable balance.");
+    pub fn get_owner(&self) -> AccountId {
+        self.owner.clone()
     }
 
-    fn _only_owner(&mut self) {
-        assert_eq!(env::signer_account_id(), env::current_account_id(), "Only contract owner can call this method.");
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
     }
 
-    /// This is a helper function with the promises happening.
-    /// The predecessor will be this account calling itself after transferring
-    /// fungible tokens. Used for functions called via promises where we
-    /// do not want end user accounts calling them directly.
-    fn _only_owner_predecessor(&mut self) {
-        assert_eq!(env::predecessor_account_id(), env::current_account_id(), "Only contract owner can sign transactions for this method.");
+    pub fn grant_role(&mut self, role: Role, account: AccountId) {
+        self._only_owner();
+        assert!(env::is_valid_account_id(account.as_bytes()), "Account ID is invalid");
+        if self._role_members_mut(role).insert(&account) {
+            events::emit(OracleEventKind::RoleGranted(vec![RoleData { role, account }]));
+        }
     }
 
-    fn _only_authorized_node(&mut self) {
-        assert!(self.authorized_nodes.contains(&env::signer_account_id()) || env::signer_account_id() == env::current_account_id(),
-            "Not an authorized node to fulfill requests.");
+    pub fn revoke_role(&mut self, role: Role, account: AccountId) {
+        self._only_owner();
+        if self._role_members_mut(role).remove(&account) {
+            events::emit(OracleEventKind::RoleRevoked(vec![RoleData { role, account }]));
+        }
     }
 
-    fn _check_callback_address(&mut self, callback_address: &AccountId) {
-        assert_ne!(callback_address, &self.link_account, "Cannot callback to LINK.");
-        assert_ne!(callback_address, &env::current_account_id(), "Callback address cannot be the oracle contract.");
+    pub fn has_role(&self, role: Role, account: AccountId) -> bool {
+        self._role_members(role).contains(&account)
     }
 
-    /// This method is not compile to the smart contract. It is used in tests only.
-    #[cfg(not(target_arch = "wasm32"))]
-    pub fn get_all_authorizations(&self) -> Vec<AccountId> {
-        let nodes_vectorized = self.authorized_nodes.as_vector();
-        let length = nodes_vectorized.len();
-        let mut ret = Vec::new();
-        for idx in 0..length {
-            ret.push(nodes_vectorized.get(idx).unwrap());
-        }
-        ret
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self._role_members(role).to_vec()
     }
 
-    /// This method is not compile to the smart contract. It is used in tests only.
-    #[cfg(not(target_arch = "wasm32"))]
-    pub fn test_callback(&self, data: Vec<u8>) {
-        println!("Received test callback with data: {:?}", data);
+    /// First step of an ownership transfer. The new owner has to call `accept_owner` to take over.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        self._only_owner();
+        assert!(env::is_valid_account_id(new_owner.as_bytes()), "New owner's account ID is invalid");
+        self.pending_owner = Some(new_owner);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::{MockedBlockchain, StorageUsage};
-    use near_sdk::{testing_env, VMContext};
-    use base64::{encode};
+    /// Second step of an ownership transfer, called by the proposed owner
+    pub fn accept_owner(&mut self) {
+        let pending_owner = self.pending_owner.clone();
+        assert_eq!(pending_owner, Some(env::predecessor_account_id()), "Only the proposed owner can accept ownership.");
+        let previous_owner = std::mem::replace(&mut self.owner, pending_owner.unwrap());
+        self.pending_owner = None;
+        events::emit(OracleEventKind::OwnershipTransferred(vec![OwnershipData {
+            previous_owner,
+            new_owner: self.owner.clone(),
+        }]));
+    }
 
-    fn link() -> AccountId { "link_near".to_string() }
-    fn alice() -> AccountId { "alice_near".to_string() }
+    pub fn reset(&mut self) {
+        self._only_owner();
+        self.requests.clear();
+        self.request_ids.clear();
+        self.fulfilling.clear();
+        events::emit(OracleEventKind::Reset);
+    }
+
+    /// Can be called after a cross-contract call before enforcing a panic
+    pub fn panic(&mut self, error_message: String) {
+        self._only_owner_predecessor();
+        env::panic(error_message.as_bytes());
+    }
+
+    fn _has_available_funds(&mut self, amount: u128) {
+        assert!(self.withdrawable_tokens >= amount, "Amount requested is greater than withdrawable balance.");
+    }
+
+    /// Removes a request after its consumer callback ran, crediting its payment to `node` and the owner's fee.
+    /// Requests whose callback failed are moved to `failed_fulfillments`.
+    /// A request removed in the meantime, e.g. by `reset`, is only logged.
+    fn _complete_fulfillment(&mut self, account: AccountId, nonce: u128, callback_succeeded: bool, node: AccountId) {
+        let request_id = self._request_id(&account, nonce);
+        self.fulfilling.remove(&request_id);
+        let account_requests = self.requests.get(&account);
+        let request_option = account_requests.as_ref().and_then(|account_requests| account_requests.get(&nonce));
+        if request_option.is_none() {
+            env::log(format!("Request {} of {} is no longer stored, its fulfillment can't be completed.", nonce, account).as_bytes());
+            return
+        }
+        // Remove request from state
+        let mut account_requests = account_requests.unwrap();
+        let request = request_option.unwrap();
+        account_requests.remove(&nonce);
+        // Must overwrite the new TreeMap with the account key
+        self.requests.insert(&account, &account_requests);
+        self.request_ids.remove(&request_id);
+        self._credit_payment(&node, request.payment);
+
+        if callback_succeeded {
+            events::emit(OracleEventKind::RequestFulfilled(vec![RequestFulfilledData {
+                account,
+                nonce: U128(nonce),
+                payment: U128(request.payment),
+                node,
+            }]));
+            return
+        }
+
+        let reason = format!("Callback {} on {} failed.", request.callback_method, request.callback_address);
+        events::emit(OracleEventKind::CallbackFailed(vec![CallbackFailedData {
+            account: account.clone(),
+            nonce: U128(nonce),
+            payment: U128(request.payment),
+            node,
+            reason: reason.clone(),
+        }]));
+        let failed_fulfillment = FailedFulfillment {
+            request,
+            reason,
+            failed_at: env::block_timestamp(),
+        };
+        let account_failures_entry = self.failed_fulfillments.get(&account);
+        let mut account_failures = if account_failures_entry.is_none() {
+            // Prefixed so it does not share storage with the account's requests TreeMap
+            TreeMap::new(format!("failed:{}", account).into_bytes())
+        } else {
+            account_failures_entry.unwrap()
+        };
+        account_failures.insert(&nonce, &failed_fulfillment);
+        self.failed_fulfillments.insert(&account, &account_failures);
+    }
+
+    /// Splits a payment between the owner's fee and the node, the oracle itself counts as the owner
+    fn _credit_payment(&mut self, node: &AccountId, payment: u128) {
+        if *node == env::current_account_id() {
+            self.withdrawable_tokens += payment;
+            return
+        }
+        let fee = payment * self.owner_fee_percentage as u128 / 100;
+        self.withdrawable_tokens += fee;
+        let earnings = self.node_earnings.get(node).unwrap_or(0);
+        self._set_node_earnings(node, earnings + payment - fee);
+    }
+
+    fn _set_node_earnings(&mut self, node: &AccountId, earnings: u128) {
+        if earnings == 0 {
+            self.node_earnings.remove(node);
+        } else {
+            self.node_earnings.insert(node, &earnings);
+        }
+    }
+
+    fn _request_id(&self, account: &AccountId, nonce: u128) -> RequestId {
+        request_id(&env::current_account_id(), account, nonce)
+    }
+
+    fn _requests_json(&self, nonce: u128, request: OracleRequest) -> RequestsJSON {
+        RequestsJSON {
+            nonce: U128(nonce),
+            request_id: self._request_id(&request.caller_account, nonce),
+            request,
+        }
+    }
+
+    fn _is_expired(&self, request: &OracleRequest) -> bool {
+        request.expiration <= env::block_timestamp()
+    }
+
+    fn _only_owner(&mut self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only contract owner can call this method.");
+    }
+
+    fn _assert_not_paused(&self, target: PauseTarget) {
+        let paused = match target {
+            PauseTarget::Intake => self.pause_state.intake,
+            PauseTarget::Fulfillment => self.pause_state.fulfillment,
+            PauseTarget::Withdrawals => self.pause_state.withdrawals,
+        };
+        assert!(!paused, "{:?} is paused.", target);
+    }
+
+    fn _only_owner_or_role(&mut self, role: Role) {
+        let predecessor = env::predecessor_account_id();
+        assert!(predecessor == self.owner || self._role_members(role).contains(&predecessor),
+            "Only contract owner or {:?} can call this method.", role);
+    }
+
+    fn _role_members(&self, role: Role) -> &UnorderedSet<AccountId> {
+        match role {
+            Role::NodeManager => &self.node_managers,
+            Role::Treasurer => &self.treasurers,
+            Role::Pauser => &self.pausers,
+        }
+    }
+
+    fn _role_members_mut(&mut self, role: Role) -> &mut UnorderedSet<AccountId> {
+        match role {
+            Role::NodeManager => &mut self.node_managers,
+            Role::Treasurer => &mut self.treasurers,
+            Role::Pauser => &mut self.pausers,
+        }
+    }
+
+    /// This is a helper function with the promises happening.
+    /// The predecessor will be this account calling itself after transferring
+    /// fungible tokens. Used for functions called via promises where we
+    /// do not want end user accounts calling them directly.
+    fn _only_owner_predecessor(&mut self) {
+        assert_eq!(env::predecessor_account_id(), env::current_account_id(), "Only contract owner can sign transactions for this method.");
+    }
+
+    fn _only_authorized_node(&mut self) {
+        assert!(self.authorized_nodes.contains(&env::signer_account_id()) || env::signer_account_id() == env::current_account_id(),
+            "Not an authorized node to fulfill requests.");
+    }
+
+    /// Validates a new request of `sender` before its payment is taken
+    fn _check_request(&mut self, sender: &AccountId, payment: u128, spec_id: &Base64String, callback_address: &AccountId, nonce: u128) {
+        self._check_callback_address(callback_address);
+        self._check_job_spec(spec_id, payment);
+
+        let entry_option = self.requests.get(sender);
+        if entry_option.is_some() {
+            // Ensure there isn't already the same nonce
+            let nonce_entry = entry_option.unwrap();
+            if nonce_entry.contains_key(&nonce) {
+                env::panic(b"Existing account and nonce in requests");
+            }
+        }
+
+        let last_nonce_option: Option<U128> = self.get_nonce(sender.clone());
+        if last_nonce_option.is_some() {
+            let last_nonce_u128: u128 = last_nonce_option.unwrap().into();
+            assert!(last_nonce_u128 < nonce, "Invalid, already used nonce: {:?}", nonce);
+        }
+    }
+
+    /// Whether `nonce` is unused and after the last nonce of `sender`, as `_check_request` requires
+    fn _is_nonce_available(&self, sender: &AccountId, nonce: u128) -> bool {
+        let existing = self.requests.get(sender).map_or(false, |account_requests| account_requests.contains_key(&nonce));
+        let last_nonce: Option<u128> = self.nonces.get(sender).map(|last_nonce| last_nonce.into());
+        !existing && last_nonce.map_or(true, |last_nonce| last_nonce < nonce)
+    }
+
+    fn _answer_schema(&self, spec_id: &Base64String) -> Option<Vec<AnswerType>> {
+        self.job_specs.get(spec_id).and_then(|job_spec| job_spec.answer_schema)
+    }
+
+    fn _check_job_spec(&self, spec_id: &Base64String, payment: u128) {
+        let job_spec_option = self.job_specs.get(spec_id);
+        if job_spec_option.is_none() {
+            env::panic(format!("Unknown job spec: {}", spec_id).as_bytes());
+        }
+        let job_spec = job_spec_option.unwrap();
+        assert!(job_spec.enabled, "Job spec is disabled.");
+        assert!(payment >= job_spec.min_payment, "Payment is below the minimum for this job spec: {}", job_spec.min_payment);
+    }
+
+    /// Returns the callback gas of a request, panicking if it's outside the bounds
+    fn _callback_gas(&self, callback_gas: Option<U64>) -> u64 {
+        callback_gas.map_or(DEFAULT_CALLBACK_GAS, |gas| gas.into())
+    }
+
+    fn _check_callback_gas(&self, callback_gas: Option<U64>) -> u64 {
+        let callback_gas = self._callback_gas(callback_gas);
+        assert!(
+            callback_gas >= self.min_callback_gas && callback_gas <= self.max_callback_gas,
+            "Callback gas must be between {} and {}.", self.min_callback_gas, self.max_callback_gas
+        );
+        callback_gas
+    }
+
+    fn _check_request_params(&self, data_version: u128, data: &str) {
+        if let Err(error) = RequestParams::from_base64(data_version, data) {
+            env::panic(format!("Invalid request data: {}", error).as_bytes());
+        }
+    }
+
+    fn _check_callback_address(&mut self, callback_address: &AccountId) {
+        assert_ne!(callback_address, &self.link_account, "Cannot callback to LINK.");
+        assert_ne!(callback_address, &env::current_account_id(), "Callback address cannot be the oracle contract.");
+    }
+
+    /// This method is not compile to the smart contract. It is used in tests only.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_all_authorizations(&self) -> Vec<AccountId> {
+        let nodes_vectorized = self.authorized_nodes.as_vector();
+        let length = nodes_vectorized.len();
+        let mut ret = Vec::new();
+        for idx in 0..length {
+            ret.push(nodes_vectorized.get(idx).unwrap());
+        }
+        ret
+    }
+
+    /// This method is not compile to the smart contract. It is used in tests only.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn test_callback(&self, data: Vec<u8>) {
+        println!("Received test callback with data: {:?}", data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::{MockedBlockchain, StorageUsage};
+    use near_sdk::{testing_env, VMContext};
+    use base64::{encode};
+    use oracle_types::interfaces::ext_ft_receiver;
+
+    fn link() -> AccountId { "link_near".to_string() }
+    fn alice() -> AccountId { "alice_near".to_string() }
     fn bob() -> AccountId { "bob_near".to_string() }
 
+    fn params(symbol: &str) -> Base64String {
+        let url = format!("https://min-api.cryptocompare.com/data/price?fsym={}&tsyms=USD", symbol);
+        RequestParams::new(&url).path("USD").times(100).to_base64()
+    }
+
     fn get_context(signer_account_id: AccountId, storage_usage: StorageUsage) -> VMContext {
         VMContext {
             current_account_id: alice(),
@@ -529,6 +1261,17 @@ mod tests {
         }
     }
 
+    /// Runs `fulfillment_post_callback` as the contract itself, with `result` as the outcome of the consumer callback
+    fn post_callback(contract: &mut Oracle, account: AccountId, nonce: u128, node: AccountId, result: PromiseResult) {
+        let context = get_context(node.clone(), env::storage_usage());
+        let storage = match env::take_blockchain_interface() {
+            Some(mut blockchain) => blockchain.as_mut_mocked_blockchain().unwrap().take_storage(),
+            None => Default::default(),
+        };
+        env::set_blockchain_interface(Box::new(MockedBlockchain::new(context, Default::default(), Default::default(), vec![result], storage)));
+        contract.fulfillment_post_callback(account, U128(nonce), node);
+    }
+
     #[test]
     fn make_request_validate_commitment() {
         let context = get_context(alice(), 0);
@@ -539,16 +1282,16 @@ mod tests {
         let spec_id = encode("unique spec id".to_string());
         let nonce = 1_u128;
         let nonce_json: U128 = nonce.into();
-        let data_version_json: U128 = 131_u128.into();
-        let data = encode("BAT".to_string());
-        contract.store_request( sender, payment_json, spec_id, "callback.sender.testnet".to_string(), "my_callback_fn".to_string(), nonce_json, data_version_json, data);
+        let data_version_json: U128 = 1_u128.into();
+        let data = params("BAT");
+        contract.store_request( sender, payment_json, spec_id, "callback.sender.testnet".to_string(), "my_callback_fn".to_string(), nonce_json, data_version_json, data, None);
 
         // second validate the serialized requests
         let max_requests: U64 = 1u64.into();
         let serialized_output = contract.get_requests(alice(), max_requests);
         let expiration_string = contract.requests.get(&alice()).unwrap().get(&nonce).unwrap().expiration.to_string();
-        let expected_before_expiration = "[{\"nonce\":\"1\",\"request\":{\"caller_account\":\"alice_near\",\"request_spec\":\"dW5pcXVlIHNwZWMgaWQ=\",\"callback_address\":\"callback.sender.testnet\",\"callback_method\":\"my_callback_fn\",\"data\":\"QkFU\",\"payment\":51319,\"expiration\":";
-        let expected_after_expiration = "}}]";
+        let expected_before_expiration = format!("[{{\"nonce\":\"1\",\"request_id\":\"{}\",\"request\":{{\"caller_account\":\"alice_near\",\"request_spec\":\"dW5pcXVlIHNwZWMgaWQ=\",\"callback_address\":\"callback.sender.testnet\",\"callback_method\":\"my_callback_fn\",\"data_version\":1,\"data\":\"{}\",\"payment\":51319,\"expiration\":", contract.get_request_id(alice(), nonce_json), params("BAT"));
+        let expected_after_expiration = ",\"callback_gas\":50000000000000}}]";
         let expected_result = format!("{}{}{}", expected_before_expiration, expiration_string, expected_after_expiration);
         let output_string = serde_json::to_string(serialized_output.as_slice());
         assert_eq!(expected_result, output_string.unwrap());
@@ -560,24 +1303,24 @@ mod tests {
     )]
     fn make_duplicate_request() {
         let mut context = get_context(alice(), 0);
-        context.attached_deposit = TRANSFER_FROM_NEAR_COST;
         testing_env!(context.clone());
         let mut contract = Oracle::new(link(), alice());
         let payment: U128 = 6_u128.into();
         let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 1_u128.into(), EXPIRY_TIME.into(), true);
         let callback_address = "callback.testnet".to_string();
         let callback_method = "test_callback".to_string();
         let nonce: U128 = 1_u128.into();
-        let data_version: U128 = 131_u128.into();
-        let data = encode("BAT".to_string());
+        let data_version: U128 = 1_u128.into();
+        let data = params("BAT");
 
-        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce.clone(), data_version.clone(), data.clone());
+        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce.clone(), data_version.clone(), data.clone(), None);
         context.prepaid_gas = 10u64.pow(18);
-        contract.store_request(alice(), payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce.clone(), data_version.clone(), data.clone());
+        contract.store_request(alice(), payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce.clone(), data_version.clone(), data.clone(), None);
         testing_env!(context.clone());
 
-        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce.clone(), data_version.clone(), data.clone());
-        contract.store_request(alice(), payment, spec_id, callback_address, callback_method, nonce, data_version, data);
+        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce.clone(), data_version.clone(), data.clone(), None);
+        contract.store_request(alice(), payment, spec_id, callback_address, callback_method, nonce, data_version, data, None);
     }
 
     #[test]
@@ -586,19 +1329,19 @@ mod tests {
     )]
     fn make_invalid_nonce_request() {
         let mut context = get_context(alice(), 0);
-        context.attached_deposit = TRANSFER_FROM_NEAR_COST;
         testing_env!(context.clone());
         let mut contract = Oracle::new(link(), alice());
         let payment: U128 = 6_u128.into();
         let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 1_u128.into(), EXPIRY_TIME.into(), true);
         let callback_address = "callback.testnet".to_string();
         let callback_method = "test_callback".to_string();
-        let data_version: U128 = 131_u128.into();
-        let data = encode("BAT".to_string());
+        let data_version: U128 = 1_u128.into();
+        let data = params("BAT");
 
-        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), 8_u128.into(), data_version.clone(), data.clone());
+        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), 8_u128.into(), data_version.clone(), data.clone(), None);
         context.prepaid_gas = 10u64.pow(18);
-        contract.store_request(alice(), payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), 8_u128.into(), data_version.clone(), data.clone());
+        contract.store_request(alice(), payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), 8_u128.into(), data_version.clone(), data.clone(), None);
         testing_env!(context.clone());
 
         let default: U128 = 0_u128.into();
@@ -608,8 +1351,8 @@ mod tests {
         let current_mapped_nonce: U128 = *contract.get_nonces().get(&alice()).clone().unwrap_or(&default);
         assert_eq!(current_mapped_nonce, 8_u128.into());
 
-        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), 7_u128.into(), data_version.clone(), data.clone());
-        contract.store_request(alice(), payment, spec_id, callback_address, callback_method, 7_u128.into(), data_version, data);
+        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), 7_u128.into(), data_version.clone(), data.clone(), None);
+        contract.store_request(alice(), payment, spec_id, callback_address, callback_method, 7_u128.into(), data_version, data, None);
     }
 
     #[test]
@@ -639,16 +1382,16 @@ mod tests {
         // Set up contract
         let mut contract = Oracle::new(link(), alice());
         // Alice stores two requests
-        contract.store_request( alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 131_u128.into(), "BAT".to_string());
-        contract.store_request( alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 131_u128.into(), "NEAR".to_string());
+        contract.store_request( alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.store_request( alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("NEAR"), None);
         // Context: Bob
         context = get_context(bob(), env::storage_usage());
         testing_env!(context);
-        contract.store_request( bob(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 131_u128.into(), "BAT".to_string());
+        contract.store_request( bob(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
         // Context: Link
         context = get_context(link(), env::storage_usage());
         testing_env!(context);
-        contract.store_request( link(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 131_u128.into(), "BAT".to_string());
+        contract.store_request( link(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
 
         let max_num_accounts: U64 = 2u64.into();
         let mut json_result = contract.get_requests_summary(max_num_accounts);
@@ -665,23 +1408,23 @@ mod tests {
 
     #[test]
     fn add_request_fulfill() {
-        let mut context = get_context(alice(), 0);
-        context.attached_deposit = TRANSFER_FROM_NEAR_COST;
+        let context = get_context(alice(), 0);
         testing_env!(context);
         let mut contract = Oracle::new(link(), alice());
         // make request
         let payment: U128 = 6_u128.into();
         let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 1_u128.into(), EXPIRY_TIME.into(), true);
         let callback_address = "callback.testnet".to_string();
         let callback_method = "test_callback".to_string();
         let nonce= 1_u128;
         let nonce_json: U128 = nonce.into();
-        let data_version: U128 = 131_u128.into();
-        let data = encode("BAT".to_string());
+        let data_version: U128 = 1_u128.into();
+        let data = params("BAT");
 
         println!("Number of requests: {}", contract.requests.len());
-        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce_json.clone(), data_version, data.clone());
-        contract.store_request(alice(), payment, spec_id, callback_address.clone(), callback_method.clone(), nonce_json.clone(), data_version, data.clone());
+        contract.request(payment.clone(), spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce_json.clone(), data_version, data.clone(), None);
+        contract.store_request(alice(), payment, spec_id, callback_address.clone(), callback_method.clone(), nonce_json.clone(), data_version, data.clone(), None);
         let max_num_accounts: U64 = 1u64.into();
         println!("{}", serde_json::to_string(contract.get_requests_summary(max_num_accounts).as_slice()).unwrap());
         // authorize bob
@@ -692,4 +1435,1041 @@ mod tests {
         testing_env!(context);
         contract.fulfill_request(alice(), 1.into(), data);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn request_ids() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        let alice_request_id = contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None).unwrap();
+        contract.store_request(bob(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        assert_eq!(alice_request_id, contract.get_request_id(alice(), 1_u128.into()));
+        assert_eq!(alice_request_id, request_id(&alice(), &alice(), 1));
+        assert_ne!(alice_request_id, contract.get_request_id(alice(), 2_u128.into()));
+        assert_ne!(alice_request_id, contract.get_request_id(bob(), 1_u128.into()));
+        assert_ne!(alice_request_id, request_id(&bob(), &alice(), 1));
+
+        let found = contract.get_request_by_id(alice_request_id.clone()).unwrap();
+        assert_eq!(U128(1), found.nonce);
+        assert_eq!(alice_request_id, found.request_id);
+        assert_eq!(alice(), found.request.caller_account);
+        assert!(contract.get_request_by_id(contract.get_request_id(alice(), 2_u128.into())).is_none());
+    }
+
+    #[test]
+    fn fulfill_request_by_id() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        let request_id = contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None).unwrap();
+        contract.add_authorization(bob());
+
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        contract.fulfill_request_by_id(request_id.clone(), encode("19.1"));
+        post_callback(&mut contract, alice(), 1, bob(), PromiseResult::Successful(vec![]));
+        assert!(contract.get_request_by_id(request_id).is_none());
+        assert_eq!(U128(6), contract.get_node_earnings(bob()));
+    }
+
+    /// Compile-time check that `ext_oracle` and `ext_ft_receiver` in oracle-types take the arguments of
+    /// the contract methods, and that those return what the interfaces declare
+    #[test]
+    fn interfaces_match_contract() {
+        let _request = |contract: &mut Oracle, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: Option<U64>| -> Promise {
+            ext_oracle::request(payment, spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce, data_version, data.clone(), callback_gas, &alice(), 0, 0);
+            contract.request(payment, spec_id, callback_address, callback_method, nonce, data_version, data, callback_gas)
+        };
+        let _store_request = |contract: &mut Oracle, sender: AccountId, payment: U128, spec_id: Base64String, callback_address: AccountId, callback_method: String, nonce: U128, data_version: U128, data: Base64String, callback_gas: Option<U64>| -> Option<RequestId> {
+            ext_oracle::store_request(sender.clone(), payment, spec_id.clone(), callback_address.clone(), callback_method.clone(), nonce, data_version, data.clone(), callback_gas, &alice(), 0, 0);
+            contract.store_request(sender, payment, spec_id, callback_address, callback_method, nonce, data_version, data, callback_gas)
+        };
+        let _request_batch = |contract: &mut Oracle, nonce: U128, requests: Vec<BatchRequest>| -> Promise {
+            ext_oracle::request_batch(nonce, requests.clone(), &alice(), 0, 0);
+            contract.request_batch(nonce, requests)
+        };
+        let _store_requests = |contract: &mut Oracle, sender: AccountId, nonce: U128, requests: Vec<BatchRequest>| -> Vec<Option<RequestId>> {
+            ext_oracle::store_requests(sender.clone(), nonce, requests.clone(), &alice(), 0, 0);
+            contract.store_requests(sender, nonce, requests)
+        };
+        let _fulfillment_post_callback = |contract: &mut Oracle, account: AccountId, nonce: U128, node: AccountId| {
+            ext_oracle::fulfillment_post_callback(account.clone(), nonce, node.clone(), &alice(), 0, 0);
+            contract.fulfillment_post_callback(account, nonce, node)
+        };
+        let _post_cancel_request = |contract: &mut Oracle, account: AccountId, nonce: U128, request: OracleRequest| {
+            ext_oracle::post_cancel_request(account.clone(), nonce, request.clone(), &alice(), 0, 0);
+            contract.post_cancel_request(account, nonce, request)
+        };
+        let _post_withdraw = |contract: &mut Oracle, recipient: AccountId, amount: U128| {
+            ext_oracle::post_withdraw(recipient.clone(), amount, &alice(), 0, 0);
+            contract.post_withdraw(recipient, amount)
+        };
+        let _post_withdraw_earnings = |contract: &mut Oracle, node: AccountId, amount: U128| {
+            ext_oracle::post_withdraw_earnings(node.clone(), amount, &alice(), 0, 0);
+            contract.post_withdraw_earnings(node, amount)
+        };
+        let _ft_on_transfer = |contract: &mut Oracle, sender_id: AccountId, amount: U128, msg: String| -> U128 {
+            ext_ft_receiver::ft_on_transfer(sender_id.clone(), amount, msg.clone(), &alice(), 0, 0);
+            contract.ft_on_transfer(sender_id, amount, msg)
+        };
+    }
+
+    fn batch_request(symbol: &str, payment: u128) -> BatchRequest {
+        BatchRequest {
+            payment: U128(payment),
+            spec_id: "unique-id".to_string(),
+            callback_address: "callback.testnet".to_string(),
+            callback_method: "test_callback".to_string(),
+            data_version: U128(1),
+            data: params(symbol),
+            callback_gas: None,
+        }
+    }
+
+    #[test]
+    fn request_batch() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        let requests = vec![batch_request("BAT", 6), batch_request("ETH", 7), batch_request("BTC", 8)];
+        contract.request_batch(5_u128.into(), requests.clone());
+
+        let request_ids = contract.store_requests(alice(), 5_u128.into(), requests);
+        assert_eq!(vec![Some(contract.get_request_id(alice(), 5_u128.into())), Some(contract.get_request_id(alice(), 6_u128.into())), Some(contract.get_request_id(alice(), 7_u128.into()))], request_ids);
+        let stored = contract.requests.get(&alice()).unwrap();
+        assert_eq!(vec![5, 6, 7], stored.iter().map(|(nonce, _)| nonce).collect::<Vec<u128>>());
+        assert_eq!(7, stored.get(&6).unwrap().payment);
+        assert_eq!(params("BTC"), stored.get(&7).unwrap().data);
+        assert_eq!(Some(U128(7)), contract.get_nonce(alice()));
+    }
+
+    #[test]
+    fn store_requests_with_taken_nonces() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        let requests = vec![batch_request("BAT", 6), batch_request("ETH", 7), batch_request("BTC", 8)];
+        contract.request_batch(5_u128.into(), requests.clone());
+
+        // a request stored while the batch's transfer was in flight takes nonce 6, so 5 is already used too
+        contract.store_request(alice(), 9_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 6_u128.into(), 1_u128.into(), params("NEAR"), None);
+        let request_ids = contract.store_requests(alice(), 5_u128.into(), requests);
+        assert_eq!(vec![None, None, Some(contract.get_request_id(alice(), 7_u128.into()))], request_ids);
+        let stored = contract.requests.get(&alice()).unwrap();
+        assert_eq!(9, stored.get(&6).unwrap().payment);
+        assert_eq!(params("NEAR"), stored.get(&6).unwrap().data);
+        assert_eq!(Some(U128(7)), contract.get_nonce(alice()));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The batch nonces are out of range."
+    )]
+    fn request_batch_nonce_overflow() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.request_batch(U128(u128::MAX), vec![batch_request("BAT", 6), batch_request("ETH", 6)]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid, already used nonce: 1"
+    )]
+    fn request_batch_with_used_nonce() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.request_batch(1_u128.into(), vec![batch_request("ETH", 6), batch_request("BTC", 6)]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid request data: Request get must be an http or https URL"
+    )]
+    fn request_batch_with_invalid_data() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        let mut invalid = batch_request("ETH", 6);
+        invalid.data = RequestParams::new("ftp://example.com").to_base64();
+        contract.request_batch(1_u128.into(), vec![batch_request("BAT", 6), invalid]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The batch can have at most 10 requests."
+    )]
+    fn request_batch_too_large() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.request_batch(1_u128.into(), vec![batch_request("BAT", 6); MAX_BATCH_REQUESTS + 1]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The batch has no requests."
+    )]
+    fn request_empty_batch() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.request_batch(1_u128.into(), vec![]);
+    }
+
+    fn batch(fulfillments: &[(AccountId, u128)]) -> Vec<FulfillmentArgs> {
+        fulfillments.iter().map(|(account, nonce)| FulfillmentArgs {
+            account: account.clone(),
+            nonce: U128(*nonce),
+            data: encode("19.1"),
+        }).collect()
+    }
+
+    #[test]
+    fn fulfill_requests() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("ETH"), None);
+        contract.store_request(bob(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BTC"), None);
+        contract.add_authorization(bob());
+
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        // the unknown nonce and the duplicate are skipped, no joint promise is returned
+        let scheduled: Vec<bool> = contract.fulfill_requests(batch(&[(alice(), 1), (alice(), 9), (bob(), 1), (alice(), 1)]));
+        assert_eq!(vec![true, false, true, false], scheduled);
+        post_callback(&mut contract, alice(), 1, bob(), PromiseResult::Successful(vec![]));
+        post_callback(&mut contract, bob(), 1, bob(), PromiseResult::Successful(vec![]));
+        assert_eq!(U128(12), contract.get_node_earnings(bob()));
+        let remaining = contract.get_requests(alice(), 10u64.into());
+        assert_eq!(1, remaining.len());
+        assert_eq!(U128(2), remaining[0].nonce);
+        assert!(contract.get_requests(bob(), 10u64.into()).is_empty());
+    }
+
+    #[test]
+    fn fulfill_requests_all_skipped() {
+        let mut contract = schema_contract();
+        assert_eq!(vec![false, false], contract.fulfill_requests(batch(&[(alice(), 1), (bob(), 1)])));
+        assert_eq!(1, contract.get_requests(alice(), 10u64.into()).len());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Must provide consumer enough gas, 200000000000000 is required for the callbacks."
+    )]
+    fn fulfill_requests_without_enough_callback_gas() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("ETH"), None);
+        contract.add_authorization(bob());
+        let mut context = get_context(bob(), env::storage_usage());
+        context.prepaid_gas = 150_000_000_000_000;
+        testing_env!(context);
+        contract.fulfill_requests(batch(&[(alice(), 1), (alice(), 2)]));
+    }
+
+    #[test]
+    fn request_callback_gas() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), Some(U64(150_000_000_000_000)));
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("BAT"), None);
+        let requests = contract.get_requests(alice(), 10u64.into());
+        assert_eq!(150_000_000_000_000, requests[0].request.callback_gas);
+        assert_eq!(DEFAULT_CALLBACK_GAS, requests[1].request.callback_gas);
+
+        contract.set_callback_gas_bounds(U64(1), U64(10));
+        assert_eq!((U64(1), U64(10)), contract.get_callback_gas_bounds());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Callback gas must be between 5000000000000 and 200000000000000."
+    )]
+    fn request_too_much_callback_gas() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.request(6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), Some(U64(250_000_000_000_000)));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Minimum callback gas can't be greater than the maximum."
+    )]
+    fn invalid_callback_gas_bounds() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_callback_gas_bounds(U64(10), U64(1));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Must provide consumer enough gas, 200000000000000 is required for the callbacks."
+    )]
+    fn fulfill_without_enough_callback_gas() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), Some(U64(150_000_000_000_000)));
+        contract.add_authorization(bob());
+        let mut context = get_context(bob(), env::storage_usage());
+        context.prepaid_gas = 150_000_000_000_000;
+        testing_env!(context);
+        contract.fulfill_request(alice(), 1_u128.into(), encode("19.1"));
+    }
+
+    fn schema_contract() -> Oracle {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.set_answer_schema("unique-id".to_string(), Some(vec![AnswerType::Int256, AnswerType::Bool]));
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        contract
+    }
+
+    #[test]
+    fn fulfill_request_words() {
+        let mut contract = schema_contract();
+        contract.fulfill_request_words(alice(), 1_u128.into(), vec![AnswerWord::Int256("1910".to_string()), AnswerWord::Bool(true)]);
+
+        // updating the job spec keeps its schema
+        context_as(alice());
+        contract.set_job_spec("unique-id".to_string(), 1_u128.into(), EXPIRY_TIME.into(), true);
+        assert_eq!(Some(vec![AnswerType::Int256, AnswerType::Bool]), contract.get_job_spec("unique-id".to_string()).unwrap().answer_schema);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid answer: Answer word 1 must be Bool"
+    )]
+    fn fulfill_request_words_wrong_type() {
+        let mut contract = schema_contract();
+        contract.fulfill_request_words(alice(), 1_u128.into(), vec![AnswerWord::Int256("1910".to_string()), AnswerWord::String("true".to_string())]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid answer: Answer word 0 is not a valid int256"
+    )]
+    fn fulfill_request_words_invalid_int() {
+        let mut contract = schema_contract();
+        contract.fulfill_request_words(alice(), 1_u128.into(), vec![AnswerWord::Int256("19.1".to_string()), AnswerWord::Bool(true)]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The job spec has an answer schema, fulfill with fulfill_request_words."
+    )]
+    fn fulfill_opaque_answer_with_schema() {
+        let mut contract = schema_contract();
+        contract.fulfill_request(alice(), 1_u128.into(), encode("19.1"));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Unknown job spec: unknown-id"
+    )]
+    fn answer_schema_unknown_job_spec() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_answer_schema("unknown-id".to_string(), Some(vec![AnswerType::Int256]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Did not find the request ID to fulfill."
+    )]
+    fn fulfill_unknown_request_id() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.add_authorization(bob());
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        contract.fulfill_request_by_id(contract.get_request_id(alice(), 1_u128.into()), encode("19.1"));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Request is not expired."
+    )]
+    fn cancel_unexpired_request() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.cancel_request(1_u128.into());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Did not find the account to cancel."
+    )]
+    fn cancel_request_of_other_account() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(bob(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        let mut context = get_context(alice(), env::storage_usage());
+        context.block_timestamp = EXPIRY_TIME + 1;
+        testing_env!(context);
+        contract.cancel_request(1_u128.into());
+    }
+
+    #[test]
+    fn cancel_expired_request() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("NEAR"), None);
+        let mut context = get_context(alice(), env::storage_usage());
+        context.block_timestamp = EXPIRY_TIME;
+        testing_env!(context);
+        contract.cancel_request(1_u128.into());
+        let remaining = contract.get_requests(alice(), 10u64.into());
+        assert_eq!(1, remaining.len());
+        assert_eq!(U128(2), remaining[0].nonce);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Request is being fulfilled and can't be cancelled."
+    )]
+    fn cancel_request_being_fulfilled() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        contract.fulfill_request(alice(), 1_u128.into(), encode("19.1"));
+
+        // the request expires before fulfillment_post_callback runs
+        let mut context = get_context(alice(), env::storage_usage());
+        context.block_timestamp = EXPIRY_TIME;
+        testing_env!(context);
+        contract.cancel_request(1_u128.into());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Request is already being fulfilled."
+    )]
+    fn fulfill_request_twice() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        contract.fulfill_request(alice(), 1_u128.into(), encode("19.1"));
+        contract.fulfill_request(alice(), 1_u128.into(), encode("19.2"));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Request has expired and can no longer be fulfilled."
+    )]
+    fn fulfill_expired_request() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+
+        let mut context = get_context(bob(), env::storage_usage());
+        context.block_timestamp = EXPIRY_TIME;
+        testing_env!(context);
+        contract.fulfill_request(alice(), 1_u128.into(), "MTkuMQ==".to_string());
+    }
+
+    #[test]
+    fn list_expired_requests() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+
+        let mut context = get_context(alice(), env::storage_usage());
+        context.block_timestamp = EXPIRY_TIME / 2;
+        testing_env!(context);
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("NEAR"), None);
+        assert!(contract.get_expired_requests(alice(), 10u64.into()).is_empty());
+
+        let mut context = get_context(alice(), env::storage_usage());
+        context.block_timestamp = EXPIRY_TIME;
+        testing_env!(context);
+        let expired = contract.get_expired_requests(alice(), 10u64.into());
+        assert_eq!(1, expired.len());
+        assert_eq!(U128(1), expired[0].nonce);
+        assert!(contract.get_expired_requests(bob(), 10u64.into()).is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Unknown job spec: dW5pcXVlIHNwZWMgaWQ="
+    )]
+    fn request_unknown_job_spec() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.request(6_u128.into(), encode("unique spec id".to_string()), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Job spec is disabled."
+    )]
+    fn request_disabled_job_spec() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 1_u128.into(), EXPIRY_TIME.into(), false);
+        contract.request(6_u128.into(), spec_id, "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Payment is below the minimum for this job spec: 10"
+    )]
+    fn request_underpaid_job_spec() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 10_u128.into(), EXPIRY_TIME.into(), true);
+        contract.request(6_u128.into(), spec_id, "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+    }
+
+    #[test]
+    fn job_spec_expiry_time() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 1_u128.into(), 60_000_000_000u64.into(), true);
+        contract.store_request(alice(), 6_u128.into(), spec_id, "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        assert_eq!(60_000_000_000, contract.requests.get(&alice()).unwrap().get(&1).unwrap().expiration);
+    }
+
+    #[test]
+    fn failed_callback_is_recorded() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.store_request(alice(), 7_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("NEAR"), None);
+
+        post_callback(&mut contract, alice(), 1, alice(), PromiseResult::Failed);
+        post_callback(&mut contract, alice(), 2, alice(), PromiseResult::Successful(vec![]));
+
+        assert_eq!(13, contract.get_withdrawable_tokens());
+        assert!(contract.get_requests(alice(), 10u64.into()).is_empty());
+        let failures = contract.get_failed_fulfillments(alice(), 10u64.into());
+        assert_eq!(1, failures.len());
+        assert_eq!(U128(1), failures[0].nonce);
+        assert_eq!("Callback test_callback on callback.testnet failed.", failures[0].failure.reason);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner can sign transactions for this method."
+    )]
+    fn fulfillment_post_callback_not_from_contract() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        context_as(bob());
+        contract.fulfillment_post_callback(alice(), 1_u128.into(), bob());
+    }
+
+    #[test]
+    fn fulfillment_post_callback_after_reset() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+        let context = get_context(bob(), env::storage_usage());
+        testing_env!(context);
+        contract.fulfill_request(alice(), 1_u128.into(), encode("19.1"));
+        contract.reset();
+
+        post_callback(&mut contract, alice(), 1, bob(), PromiseResult::Successful(vec![]));
+        assert_eq!(U128(0), contract.get_node_earnings(bob()));
+        assert!(contract.get_failed_fulfillments(alice(), 10u64.into()).is_empty());
+    }
+
+    #[test]
+    fn node_earnings_with_owner_fee() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_owner_fee_percentage(10);
+        contract.store_request(alice(), 50_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.store_request(alice(), 20_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("NEAR"), None);
+        contract.store_request(alice(), 9_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 3_u128.into(), 1_u128.into(), params("BAT"), None);
+
+        post_callback(&mut contract, alice(), 1, bob(), PromiseResult::Successful(vec![]));
+        post_callback(&mut contract, alice(), 2, link(), PromiseResult::Failed);
+        post_callback(&mut contract, alice(), 3, bob(), PromiseResult::Successful(vec![]));
+
+        assert_eq!(U128(45 + 9), contract.get_node_earnings(bob()));
+        assert_eq!(U128(18), contract.get_node_earnings(link()));
+        assert_eq!(U128(0), contract.get_node_earnings(alice()));
+        assert_eq!(2, contract.get_all_node_earnings().len());
+        // the fee rounds down in favor of the node
+        assert_eq!(5 + 2, contract.get_withdrawable_tokens());
+
+        context_as(bob());
+        contract.withdraw_earnings(54_u128.into());
+        assert_eq!(U128(0), contract.get_node_earnings(bob()));
+        assert_eq!(vec![link()], contract.get_all_node_earnings().keys().cloned().collect::<Vec<AccountId>>());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Amount requested is greater than the node's earnings."
+    )]
+    fn withdraw_more_than_earnings() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        post_callback(&mut contract, alice(), 1, bob(), PromiseResult::Successful(vec![]));
+        context_as(bob());
+        contract.withdraw_earnings(7_u128.into());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Owner fee percentage can't be more than 100."
+    )]
+    fn owner_fee_over_100() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_owner_fee_percentage(101);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner or NodeManager can call this method."
+    )]
+    fn add_authorization_not_owner() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), bob());
+        contract.add_authorization(alice());
+    }
+
+    #[test]
+    fn owner_is_predecessor_not_contract() {
+        let mut context = get_context(alice(), 0);
+        context.predecessor_account_id = bob();
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), bob());
+        contract.add_authorization(alice());
+        assert!(contract.is_authorized(alice()));
+    }
+
+    #[test]
+    fn transfer_ownership() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.propose_owner(bob());
+        assert_eq!(alice(), contract.get_owner());
+        assert_eq!(Some(bob()), contract.get_pending_owner());
+
+        let mut context = get_context(bob(), env::storage_usage());
+        context.predecessor_account_id = bob();
+        testing_env!(context);
+        contract.accept_owner();
+        assert_eq!(bob(), contract.get_owner());
+        assert_eq!(None, contract.get_pending_owner());
+        contract.add_authorization(bob());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only the proposed owner can accept ownership."
+    )]
+    fn accept_ownership_not_proposed() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.propose_owner(bob());
+
+        let mut context = get_context(link(), env::storage_usage());
+        context.predecessor_account_id = link();
+        testing_env!(context);
+        contract.accept_owner();
+    }
+
+    fn context_as(predecessor: AccountId) {
+        let mut context = get_context(predecessor.clone(), env::storage_usage());
+        context.predecessor_account_id = predecessor;
+        testing_env!(context);
+    }
+
+    #[test]
+    fn grant_and_revoke_roles() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::NodeManager, bob());
+        contract.grant_role(Role::Treasurer, link());
+        assert!(contract.has_role(Role::NodeManager, bob()));
+        assert!(!contract.has_role(Role::Treasurer, bob()));
+        assert_eq!(vec![link()], contract.get_role_members(Role::Treasurer));
+        assert!(contract.get_role_members(Role::Pauser).is_empty());
+        contract.revoke_role(Role::NodeManager, bob());
+        assert!(contract.get_role_members(Role::NodeManager).is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner can call this method."
+    )]
+    fn grant_role_not_owner() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::NodeManager, bob());
+        context_as(bob());
+        contract.grant_role(Role::Treasurer, bob());
+    }
+
+    #[test]
+    fn node_manager_manages_nodes() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::NodeManager, bob());
+        context_as(bob());
+        contract.add_authorization(link());
+        assert!(contract.is_authorized(link()));
+        contract.remove_authorization(link());
+        assert!(!contract.is_authorized(link()));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner or Treasurer can call this method."
+    )]
+    fn node_manager_cannot_withdraw() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.withdrawable_tokens = 10;
+        contract.grant_role(Role::NodeManager, bob());
+        context_as(bob());
+        contract.withdraw(bob(), 10_u128.into());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner can call this method."
+    )]
+    fn node_manager_cannot_reset() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::NodeManager, bob());
+        context_as(bob());
+        contract.reset();
+    }
+
+    #[test]
+    fn treasurer_withdraws() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.withdrawable_tokens = 10;
+        contract.grant_role(Role::Treasurer, bob());
+        context_as(bob());
+        contract.withdraw(bob(), 10_u128.into());
+        assert_eq!(0, contract.get_withdrawable_tokens());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Amount requested is greater than withdrawable balance."
+    )]
+    fn withdraw_twice_before_transfer() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.withdrawable_tokens = 10;
+        // the first transfer is still in flight, but its amount is already deducted
+        contract.withdraw(bob(), 10_u128.into());
+        contract.withdraw(bob(), 10_u128.into());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner or NodeManager can call this method."
+    )]
+    fn treasurer_cannot_manage_nodes() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::Treasurer, bob());
+        context_as(bob());
+        contract.add_authorization(bob());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner or NodeManager can call this method."
+    )]
+    fn pauser_cannot_manage_nodes() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::Pauser, bob());
+        context_as(bob());
+        contract.remove_authorization(alice());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner or NodeManager can call this method."
+    )]
+    fn revoked_node_manager_cannot_manage_nodes() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::NodeManager, bob());
+        contract.revoke_role(Role::NodeManager, bob());
+        context_as(bob());
+        contract.add_authorization(bob());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Intake is paused."
+    )]
+    fn request_while_paused() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        let spec_id = encode("unique spec id".to_string());
+        contract.set_job_spec(spec_id.clone(), 1_u128.into(), EXPIRY_TIME.into(), true);
+        contract.grant_role(Role::Pauser, bob());
+        context_as(bob());
+        contract.set_paused(PauseTarget::Intake, true);
+        assert!(contract.get_pause_state().intake);
+        assert!(!contract.get_pause_state().fulfillment);
+        contract.request(6_u128.into(), spec_id, "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Intake is paused."
+    )]
+    fn request_batch_while_paused() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_paused(PauseTarget::Intake, true);
+        contract.request_batch(1_u128.into(), vec![batch_request("BAT", 6)]);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Fulfillment is paused."
+    )]
+    fn fulfill_while_paused() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+        contract.set_paused(PauseTarget::Fulfillment, true);
+        contract.set_paused(PauseTarget::Intake, true);
+        contract.set_paused(PauseTarget::Intake, false);
+        context_as(bob());
+        contract.fulfill_request(alice(), 1_u128.into(), "MTkuMQ==".to_string());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Fulfillment is paused."
+    )]
+    fn fulfill_requests_while_paused() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+        contract.add_authorization(bob());
+        contract.set_paused(PauseTarget::Fulfillment, true);
+        context_as(bob());
+        contract.fulfill_requests(batch(&[(alice(), 1)]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Withdrawals is paused."
+    )]
+    fn withdraw_while_paused() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.withdrawable_tokens = 10;
+        contract.set_paused(PauseTarget::Withdrawals, true);
+        contract.withdraw(bob(), 10_u128.into());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only contract owner or Pauser can call this method."
+    )]
+    fn pause_not_pauser() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.grant_role(Role::NodeManager, bob());
+        context_as(bob());
+        contract.set_paused(PauseTarget::Fulfillment, true);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid request data: Request data is not valid JSON"
+    )]
+    fn ft_on_transfer_malformed_data() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+
+        let mut context = get_context(bob(), env::storage_usage());
+        context.predecessor_account_id = link();
+        testing_env!(context);
+        let msg = format!("{{\"spec_id\":\"unique-id\",\"callback_address\":\"callback.testnet\",\"callback_method\":\"test_callback\",\"nonce\":\"1\",\"data\":\"{}\"}}", encode("BAT".to_string()));
+        contract.ft_on_transfer(bob(), 10_u128.into(), msg);
+    }
+
+    #[test]
+    fn store_request_after_callback_gas_bounds_change() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.request(6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), None);
+
+        // the LINK is already transferred when store_request runs, so the bounds aren't checked again
+        contract.set_callback_gas_bounds(U64(1), U64(10));
+        contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("BAT"), Some(U64(DEFAULT_CALLBACK_GAS)));
+        assert_eq!(DEFAULT_CALLBACK_GAS, contract.get_requests(alice(), 10u64.into())[0].request.callback_gas);
+    }
+
+    #[test]
+    fn store_request_with_taken_nonce() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        // two requests with nonce 2 were in flight, the first one to be stored keeps it
+        assert!(contract.store_request(alice(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("BAT"), None).is_some());
+        assert_eq!(None, contract.store_request(alice(), 9_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("ETH"), None));
+        // a lower nonce doesn't move the last nonce back
+        assert_eq!(None, contract.store_request(alice(), 9_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 1_u128.into(), params("ETH"), None));
+        assert_eq!(Some(U128(2)), contract.get_nonce(alice()));
+        let stored = contract.requests.get(&alice()).unwrap();
+        assert_eq!(1, stored.len());
+        assert_eq!(6, stored.get(&2).unwrap().payment);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid request data: Unsupported request data version: 131"
+    )]
+    fn request_unsupported_data_version() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.request(6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 1_u128.into(), 131_u128.into(), params("BAT"), None);
+    }
+
+    #[test]
+    fn request_with_ft_transfer_call() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+
+        let mut context = get_context(bob(), env::storage_usage());
+        context.predecessor_account_id = link();
+        testing_env!(context);
+        let msg = format!("{{\"payment\":\"6\",\"spec_id\":\"unique-id\",\"callback_address\":\"callback.testnet\",\"callback_method\":\"test_callback\",\"nonce\":\"1\",\"data\":\"{}\"}}", params("BAT"));
+        let unused = contract.ft_on_transfer(bob(), 10_u128.into(), msg);
+        assert_eq!(U128(4), unused);
+        assert_eq!(Some(U128(1)), contract.get_nonce(bob()));
+        let requests = contract.get_requests(bob(), 10u64.into());
+        assert_eq!(6, requests[0].request.payment);
+
+        // Without a payment the whole amount is used
+        let msg = format!("{{\"spec_id\":\"unique-id\",\"callback_address\":\"callback.testnet\",\"callback_method\":\"test_callback\",\"nonce\":\"2\",\"data\":\"{}\"}}", params("BAT"));
+        let unused = contract.ft_on_transfer(bob(), 10_u128.into(), msg);
+        assert_eq!(U128(0), unused);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Only LINK tokens can be transferred to the oracle."
+    )]
+    fn ft_on_transfer_not_link() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.ft_on_transfer(bob(), 10_u128.into(), "{}".to_string());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid, already used nonce: 1"
+    )]
+    fn ft_on_transfer_used_nonce() {
+        let context = get_context(alice(), 0);
+        testing_env!(context);
+        let mut contract = Oracle::new(link(), alice());
+        contract.set_job_spec("unique-id".to_string(), 5_u128.into(), EXPIRY_TIME.into(), true);
+        contract.store_request(bob(), 6_u128.into(), "unique-id".to_string(), "callback.testnet".to_string(), "test_callback".to_string(), 2_u128.into(), 1_u128.into(), params("BAT"), None);
+
+        let mut context = get_context(bob(), env::storage_usage());
+        context.predecessor_account_id = link();
+        testing_env!(context);
+        let msg = format!("{{\"spec_id\":\"unique-id\",\"callback_address\":\"callback.testnet\",\"callback_method\":\"test_callback\",\"nonce\":\"1\",\"data\":\"{}\"}}", params("BAT"));
+        contract.ft_on_transfer(bob(), 10_u128.into(), msg);
+    }
+}
@@ -0,0 +1,447 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Serialize, Deserialize};
+use near_sdk::collections::{TreeMap, UnorderedSet};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{AccountId, env, near_bindgen};
+
+#[global_allocator]
+static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
+
+/// A numbered round that authorized nodes submit answers into.
+/// `answered_in_round` is 0 until the round (or a round before it) has an answer.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Round {
+    answer: u128,
+    started_at: u64,
+    updated_at: u64,
+    answered_in_round: u64,
+    submissions: Vec<(AccountId, u128)>,
+}
+
+/// Round data in the shape of Chainlink's `latestRoundData`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RoundDataJSON {
+    round_id: U64,
+    answer: U128,
+    started_at: U64,
+    updated_at: U64,
+    answered_in_round: U64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ConfigJSON {
+    description: String,
+    decimals: u8,
+    min_submission_value: U128,
+    max_submission_value: U128,
+    min_submissions: U64,
+    heartbeat: U64,
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PriceFeed {
+    owner: AccountId,
+    description: String,
+    decimals: u8,
+    nodes: UnorderedSet<AccountId>,
+    min_submission_value: u128,
+    max_submission_value: u128,
+    // submissions needed before a round has an answer
+    min_submissions: u64,
+    // answers older than this are stale, and an unanswered round can be replaced after it (nanoseconds)
+    heartbeat: u64,
+    rounds: TreeMap<u64, Round>,
+    // round nodes are currently submitting to
+    reporting_round_id: u64,
+    // latest round with an answer of its own
+    latest_round_id: u64,
+}
+
+impl Default for PriceFeed {
+    fn default() -> Self {
+        panic!("Price feed should be initialized before usage")
+    }
+}
+
+#[near_bindgen]
+impl PriceFeed {
+    /// `nodes` are the first authorized nodes, as `min_submissions` can't be more than there are nodes
+    #[init]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(owner_id: AccountId, description: String, decimals: u8, nodes: Vec<AccountId>, min_submission_value: U128, max_submission_value: U128, min_submissions: U64, heartbeat: U64) -> Self {
+        assert!(env::is_valid_account_id(owner_id.as_bytes()), "Owner's account ID is invalid");
+        assert!(!env::state_exists(), "Already initialized");
+        let mut feed = Self {
+            owner: owner_id,
+            description,
+            decimals,
+            nodes: UnorderedSet::new(b"nodes".to_vec()),
+            min_submission_value: 0,
+            max_submission_value: 0,
+            min_submissions: 0,
+            heartbeat: 0,
+            rounds: TreeMap::new(b"r".to_vec()),
+            reporting_round_id: 0,
+            latest_round_id: 0,
+        };
+        for node in nodes {
+            feed._add_node(node);
+        }
+        feed._set_submission_bounds(min_submission_value.into(), max_submission_value.into());
+        feed._set_min_submissions(min_submissions.into());
+        feed._set_heartbeat(heartbeat.into());
+        feed
+    }
+
+    /// Submits `answer` to round `round_id`, which is either the reporting round or the next one.
+    /// The next round can be started once the reporting round has an answer, or after it has been
+    /// open for longer than the heartbeat.
+    pub fn submit(&mut self, round_id: U64, answer: U128) {
+        let node = env::predecessor_account_id();
+        assert!(self.nodes.contains(&node), "Only authorized nodes can submit answers.");
+        let round_id: u64 = round_id.into();
+        let answer: u128 = answer.into();
+        assert!(answer >= self.min_submission_value, "Submission is below the minimum value.");
+        assert!(answer <= self.max_submission_value, "Submission is above the maximum value.");
+
+        if round_id == self.reporting_round_id + 1 {
+            self._start_round(round_id);
+        } else if round_id != self.reporting_round_id || round_id == 0 {
+            env::panic(b"Invalid round to submit to.");
+        }
+
+        let mut round = self.rounds.get(&round_id).unwrap();
+        assert!(round.submissions.iter().all(|(account, _)| *account != node), "Node has already submitted to this round.");
+        round.submissions.push((node, answer));
+        if round.submissions.len() as u64 >= self.min_submissions {
+            let mut answers: Vec<u128> = round.submissions.iter().map(|(_, answer)| *answer).collect();
+            round.answer = median(&mut answers);
+            round.updated_at = env::block_timestamp();
+            round.answered_in_round = round_id;
+            self.latest_round_id = round_id;
+            env::log(format!("Round {} answer: {}", round_id, round.answer).as_bytes());
+        }
+        self.rounds.insert(&round_id, &round);
+    }
+
+    /// Returns the latest answered round. Panics if there is no answer yet.
+    pub fn latest_round_data(&self) -> RoundDataJSON {
+        if self.latest_round_id == 0 {
+            env::panic(b"No data present.");
+        }
+        self.get_round_data(self.latest_round_id.into())
+    }
+
+    /// Returns the latest answered round, and panics if it is older than the heartbeat.
+    pub fn latest_fresh_round_data(&self) -> RoundDataJSON {
+        assert!(!self.is_stale(), "Answer is stale.");
+        self.latest_round_data()
+    }
+
+    /// Returns a round by ID. A round that was replaced before it got an answer holds the
+    /// answer of an earlier round, see `answered_in_round`.
+    pub fn get_round_data(&self, round_id: U64) -> RoundDataJSON {
+        let round_id: u64 = round_id.into();
+        match self.rounds.get(&round_id) {
+            Some(round) if round.answered_in_round != 0 => RoundDataJSON {
+                round_id: round_id.into(),
+                answer: round.answer.into(),
+                started_at: round.started_at.into(),
+                updated_at: round.updated_at.into(),
+                answered_in_round: round.answered_in_round.into(),
+            },
+            _ => env::panic(b"No data present."),
+        }
+    }
+
+    /// True if there is no answer, or the latest answer is older than the heartbeat
+    pub fn is_stale(&self) -> bool {
+        match self.rounds.get(&self.latest_round_id) {
+            Some(round) => env::block_timestamp().saturating_sub(round.updated_at) > self.heartbeat,
+            None => true,
+        }
+    }
+
+    pub fn get_reporting_round_id(&self) -> U64 {
+        self.reporting_round_id.into()
+    }
+
+    pub fn get_config(&self) -> ConfigJSON {
+        ConfigJSON {
+            description: self.description.clone(),
+            decimals: self.decimals,
+            min_submission_value: self.min_submission_value.into(),
+            max_submission_value: self.max_submission_value.into(),
+            min_submissions: self.min_submissions.into(),
+            heartbeat: self.heartbeat.into(),
+        }
+    }
+
+    pub fn add_node(&mut self, node: AccountId) {
+        self._only_owner();
+        self._add_node(node);
+    }
+
+    /// A node can only be removed if enough nodes are left for `min_submissions`
+    pub fn remove_node(&mut self, node: AccountId) {
+        self._only_owner();
+        if self.nodes.remove(&node) {
+            assert!(self.min_submissions <= self.nodes.len(), "Minimum submissions can't be more than the number of nodes.");
+        }
+    }
+
+    pub fn get_nodes(&self) -> Vec<AccountId> {
+        self.nodes.to_vec()
+    }
+
+    pub fn set_submission_bounds(&mut self, min_submission_value: U128, max_submission_value: U128) {
+        self._only_owner();
+        self._set_submission_bounds(min_submission_value.into(), max_submission_value.into());
+    }
+
+    pub fn set_min_submissions(&mut self, min_submissions: U64) {
+        self._only_owner();
+        self._set_min_submissions(min_submissions.into());
+    }
+
+    pub fn set_heartbeat(&mut self, heartbeat: U64) {
+        self._only_owner();
+        self._set_heartbeat(heartbeat.into());
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner.clone()
+    }
+}
+
+impl PriceFeed {
+    fn _start_round(&mut self, round_id: u64) {
+        let now = env::block_timestamp();
+        if let Some(mut reporting_round) = self.rounds.get(&self.reporting_round_id) {
+            if reporting_round.answered_in_round != self.reporting_round_id {
+                assert!(now >= reporting_round.started_at + self.heartbeat, "Previous round is still open.");
+                // the replaced round keeps the latest answer, like a timed out FluxAggregator round
+                if let Some(latest_round) = self.rounds.get(&self.latest_round_id) {
+                    reporting_round.answer = latest_round.answer;
+                    reporting_round.updated_at = latest_round.updated_at;
+                    reporting_round.answered_in_round = latest_round.answered_in_round;
+                    self.rounds.insert(&self.reporting_round_id, &reporting_round);
+                }
+            }
+        }
+        self.rounds.insert(&round_id, &Round {
+            answer: 0,
+            started_at: now,
+            updated_at: 0,
+            answered_in_round: 0,
+            submissions: Vec::new(),
+        });
+        self.reporting_round_id = round_id;
+    }
+
+    fn _set_submission_bounds(&mut self, min_submission_value: u128, max_submission_value: u128) {
+        assert!(min_submission_value <= max_submission_value, "Minimum submission value must not be greater than the maximum.");
+        self.min_submission_value = min_submission_value;
+        self.max_submission_value = max_submission_value;
+    }
+
+    fn _add_node(&mut self, node: AccountId) {
+        assert!(env::is_valid_account_id(node.as_bytes()), "Node's account ID is invalid");
+        self.nodes.insert(&node);
+    }
+
+    fn _set_min_submissions(&mut self, min_submissions: u64) {
+        assert!(min_submissions > 0, "Minimum submissions must be greater than zero.");
+        assert!(min_submissions <= self.nodes.len(), "Minimum submissions can't be more than the number of nodes.");
+        self.min_submissions = min_submissions;
+    }
+
+    fn _set_heartbeat(&mut self, heartbeat: u64) {
+        assert!(heartbeat > 0, "Heartbeat must be greater than zero.");
+        self.heartbeat = heartbeat;
+    }
+
+    fn _only_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only contract owner can call this method.");
+    }
+}
+
+/// Median of the answers, rounded down between the two middle answers
+fn median(answers: &mut Vec<u128>) -> u128 {
+    answers.sort_unstable();
+    let middle = answers.len() / 2;
+    if answers.len() % 2 == 1 {
+        answers[middle]
+    } else {
+        let (low, high) = (answers[middle - 1], answers[middle]);
+        low + (high - low) / 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::MockedBlockchain;
+    use near_sdk::{testing_env, VMContext};
+
+    const HEARTBEAT: u64 = 3_600_000_000_000;
+
+    fn owner() -> AccountId { "price-feed.testnet".to_string() }
+    fn bob() -> AccountId { "bob.testnet".to_string() }
+    fn carol() -> AccountId { "carol.testnet".to_string() }
+    fn dave() -> AccountId { "dave.testnet".to_string() }
+
+    fn get_context(predecessor_account_id: AccountId, block_timestamp: u64) -> VMContext {
+        VMContext {
+            current_account_id: owner(),
+            signer_account_id: predecessor_account_id.clone(),
+            signer_account_pk: vec![0, 1, 2],
+            predecessor_account_id,
+            input: vec![],
+            block_index: 0,
+            block_timestamp,
+            epoch_height: 0,
+            account_balance: 0,
+            account_locked_balance: 0,
+            storage_usage: 0,
+            attached_deposit: 0,
+            prepaid_gas: 10u64.pow(18),
+            random_seed: vec![0, 1, 2],
+            is_view: false,
+            output_data_receivers: vec![],
+        }
+    }
+
+    fn context_as(predecessor: AccountId, block_timestamp: u64) {
+        let mut context = get_context(predecessor, block_timestamp);
+        context.storage_usage = env::storage_usage();
+        testing_env!(context);
+    }
+
+    fn new_feed(min_submissions: u64) -> PriceFeed {
+        testing_env!(get_context(owner(), 0));
+        PriceFeed::new(owner(), "ETH / USD".to_string(), 2, vec![bob(), carol(), dave()], U128(1), U128(1_000_000), U64(min_submissions), U64(HEARTBEAT))
+    }
+
+    #[test]
+    fn round_is_answered_with_median() {
+        let mut feed = new_feed(2);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1910));
+        assert!(feed.is_stale());
+        context_as(carol(), 20);
+        feed.submit(U64(1), U128(1930));
+        assert_eq!(RoundDataJSON {
+            round_id: U64(1),
+            answer: U128(1920),
+            started_at: U64(10),
+            updated_at: U64(20),
+            answered_in_round: U64(1),
+        }, feed.latest_round_data());
+
+        // later submissions update the answer
+        context_as(dave(), 30);
+        feed.submit(U64(1), U128(1900));
+        assert_eq!(U128(1910), feed.latest_fresh_round_data().answer);
+        assert_eq!(U64(30), feed.get_round_data(U64(1)).updated_at);
+    }
+
+    #[test]
+    #[should_panic(expected = "No data present.")]
+    fn no_data_before_min_submissions() {
+        let mut feed = new_feed(2);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1910));
+        feed.latest_round_data();
+    }
+
+    #[test]
+    #[should_panic(expected = "Submission is above the maximum value.")]
+    fn submission_above_maximum() {
+        let mut feed = new_feed(1);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1_000_001));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only authorized nodes can submit answers.")]
+    fn unauthorized_submission() {
+        let mut feed = new_feed(1);
+        context_as(owner(), 10);
+        feed.submit(U64(1), U128(1910));
+    }
+
+    #[test]
+    #[should_panic(expected = "Node has already submitted to this round.")]
+    fn double_submission() {
+        let mut feed = new_feed(2);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1910));
+        feed.submit(U64(1), U128(1920));
+    }
+
+    #[test]
+    #[should_panic(expected = "Previous round is still open.")]
+    fn new_round_while_previous_is_open() {
+        let mut feed = new_feed(2);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1910));
+        context_as(carol(), 20);
+        feed.submit(U64(2), U128(1920));
+    }
+
+    #[test]
+    fn unanswered_round_is_replaced_after_heartbeat() {
+        let mut feed = new_feed(1);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1910));
+        context_as(owner(), 10);
+        feed.set_min_submissions(U64(2));
+        context_as(bob(), 20);
+        feed.submit(U64(2), U128(1920));
+
+        context_as(carol(), 20 + HEARTBEAT);
+        feed.submit(U64(3), U128(1930));
+        assert_eq!(U64(3), feed.get_reporting_round_id());
+        // round 2 never got an answer of its own
+        let round = feed.get_round_data(U64(2));
+        assert_eq!(U128(1910), round.answer);
+        assert_eq!(U64(1), round.answered_in_round);
+        assert_eq!(U64(1), feed.latest_round_data().round_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Answer is stale.")]
+    fn stale_answer() {
+        let mut feed = new_feed(1);
+        context_as(bob(), 10);
+        feed.submit(U64(1), U128(1910));
+        context_as(bob(), 11 + HEARTBEAT);
+        feed.latest_fresh_round_data();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only contract owner can call this method.")]
+    fn set_bounds_not_owner() {
+        let mut feed = new_feed(1);
+        context_as(bob(), 10);
+        feed.set_submission_bounds(U128(0), U128(10));
+    }
+
+    #[test]
+    #[should_panic(expected = "Minimum submissions can't be more than the number of nodes.")]
+    fn min_submissions_above_nodes() {
+        let mut feed = new_feed(1);
+        context_as(owner(), 10);
+        feed.set_min_submissions(U64(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Minimum submissions can't be more than the number of nodes.")]
+    fn remove_node_below_min_submissions() {
+        let mut feed = new_feed(3);
+        context_as(owner(), 10);
+        feed.remove_node(dave());
+    }
+}